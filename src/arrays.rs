@@ -0,0 +1,267 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::value::{map_get, Value};
+use std::cmp::Ordering;
+
+/// Most elements `range` will build.
+const MAX_RANGE: i128 = 10_000_000;
+
+pub fn call(name: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+    let result = match name {
+        "sort" => expect_array(name, args, 0).map(|arr| Value::Array(sort(arr))),
+        "reverse" => reverse(args),
+        "contains" => contains(args),
+        "index_of" => index_of(args),
+        "slice" => slice(args),
+        "concat" => Ok(Value::Array(concat(args))),
+        "unique" => expect_array(name, args, 0).map(|arr| Value::Array(unique(arr))),
+        "flatten" => flatten_call(args),
+        "range" => range(args),
+        "sum" => expect_array(name, args, 0).and_then(sum),
+        "min" => Ok(extreme(args, Ordering::Less)),
+        "max" => Ok(extreme(args, Ordering::Greater)),
+        "keys" => expect_map(name, args)
+            .map(|map| Value::Array(map.iter().map(|(k, _)| Value::String(k.clone())).collect())),
+        "values" => expect_map(name, args)
+            .map(|map| Value::Array(map.iter().map(|(_, v)| v.clone()).collect())),
+        "has_key" => expect_map(name, args).map(|map| {
+            let key = args.get(1).map(|v| v.to_string()).unwrap_or_default();
+            Value::Bool(map_get(map, &key).is_some())
+        }),
+        _ => return None,
+    };
+    Some(result.map_err(Error::from))
+}
+
+fn expect_array<'a>(name: &str, args: &'a [Value], position: usize) -> Result<&'a [Value], String> {
+    match args.get(position) {
+        Some(Value::Array(arr)) => Ok(arr),
+        Some(other) => Err(format!("{}() expects an array, got '{}'", name, other)),
+        None => Err(format!("{}() expects an array argument", name)),
+    }
+}
+
+fn expect_map<'a>(name: &str, args: &'a [Value]) -> Result<&'a [(String, Value)], String> {
+    match args.first() {
+        Some(Value::Map(map)) => Ok(map),
+        Some(other) => Err(format!("{}() expects a map, got '{}'", name, other)),
+        None => Err(format!("{}() expects a map argument", name)),
+    }
+}
+
+/// Sort by `Value::sort_cmp`, so mixed arrays group by kind.
+pub fn sort(arr: &[Value]) -> Vec<Value> {
+    let mut sorted = arr.to_vec();
+    sorted.sort_by(|a, b| a.sort_cmp(b));
+    sorted
+}
+
+pub fn unique(arr: &[Value]) -> Vec<Value> {
+    let mut seen: Vec<Value> = Vec::new();
+    for item in arr {
+        if !seen.iter().any(|v| v.equals(item)) {
+            seen.push(item.clone());
+        }
+    }
+    seen
+}
+
+/// Concatenate arrays; non-array arguments are appended as single elements.
+pub fn concat(args: &[Value]) -> Vec<Value> {
+    let mut result = Vec::new();
+    for arg in args {
+        match arg {
+            Value::Array(items) => result.extend(items.iter().cloned()),
+            other => result.push(other.clone()),
+        }
+    }
+    result
+}
+
+pub fn flatten(arr: &[Value], depth: i64) -> Vec<Value> {
+    let mut result = Vec::new();
+    for item in arr {
+        match item {
+            Value::Array(inner) if depth > 0 => result.extend(flatten(inner, depth - 1)),
+            other => result.push(other.clone()),
+        }
+    }
+    result
+}
+
+fn flatten_call(args: &[Value]) -> Result<Value, String> {
+    let arr = expect_array("flatten", args, 0)?;
+    let depth = args.get(1).map(|v| v.to_int()).unwrap_or(1);
+    Ok(Value::Array(flatten(arr, depth)))
+}
+
+fn reverse(args: &[Value]) -> Result<Value, String> {
+    match args.first() {
+        Some(Value::Array(arr)) => Ok(Value::Array(arr.iter().rev().cloned().collect())),
+        Some(Value::String(s)) => Ok(Value::String(s.chars().rev().collect())),
+        Some(Value::Bytes(bytes)) => Ok(Value::Bytes(bytes.iter().rev().copied().collect())),
+        Some(other) => Err(format!(
+            "reverse() expects an array or string, got '{}'",
+            other
+        )),
+        None => Err("reverse() expects an array argument".to_string()),
+    }
+}
+
+fn contains(args: &[Value]) -> Result<Value, String> {
+    let needle = args.get(1).cloned().unwrap_or(Value::Nil);
+    let found = match args.first() {
+        Some(Value::Array(arr)) => arr.iter().any(|v| v.equals(&needle)),
+        Some(Value::String(s)) => s.contains(&needle.to_string()),
+        _ => false,
+    };
+    Ok(Value::Bool(found))
+}
+
+fn index_of(args: &[Value]) -> Result<Value, String> {
+    let needle = args.get(1).cloned().unwrap_or(Value::Nil);
+    let position = match args.first() {
+        Some(Value::Array(arr)) => arr.iter().position(|v| v.equals(&needle)),
+        Some(Value::String(s)) => s
+            .find(&needle.to_string())
+            .map(|byte_idx| s[..byte_idx].chars().count()),
+        _ => None,
+    };
+    Ok(Value::Int(position.map(|p| p as i64).unwrap_or(-1)))
+}
+
+/// Resolve a possibly negative index against `len`, clamping into range.
+fn clamp_index(index: i64, len: usize) -> usize {
+    let len = len as i64;
+    let resolved = if index < 0 { len + index } else { index };
+    resolved.clamp(0, len) as usize
+}
+
+fn slice(args: &[Value]) -> Result<Value, String> {
+    let start = args.get(1).map(|v| v.to_int()).unwrap_or(0);
+    match args.first() {
+        Some(Value::Array(arr)) => {
+            let end = args.get(2).map(|v| v.to_int()).unwrap_or(arr.len() as i64);
+            let (from, to) = (clamp_index(start, arr.len()), clamp_index(end, arr.len()));
+            Ok(Value::Array(if from < to {
+                arr[from..to].to_vec()
+            } else {
+                Vec::new()
+            }))
+        }
+        Some(Value::String(s)) => {
+            let chars: Vec<char> = s.chars().collect();
+            let end = args
+                .get(2)
+                .map(|v| v.to_int())
+                .unwrap_or(chars.len() as i64);
+            let (from, to) = (
+                clamp_index(start, chars.len()),
+                clamp_index(end, chars.len()),
+            );
+            Ok(Value::String(if from < to {
+                chars[from..to].iter().collect()
+            } else {
+                String::new()
+            }))
+        }
+        Some(Value::Bytes(bytes)) => {
+            let end = args
+                .get(2)
+                .map(|v| v.to_int())
+                .unwrap_or(bytes.len() as i64);
+            let (from, to) = (
+                clamp_index(start, bytes.len()),
+                clamp_index(end, bytes.len()),
+            );
+            Ok(Value::Bytes(if from < to {
+                bytes[from..to].to_vec()
+            } else {
+                Vec::new()
+            }))
+        }
+        Some(other) => Err(format!(
+            "slice() expects an array or string, got '{}'",
+            other
+        )),
+        None => Err("slice() expects an array argument".to_string()),
+    }
+}
+
+/// `range(end)`, `range(start, end)` or `range(start, end, step)`; `end` is exclusive.
+fn range(args: &[Value]) -> Result<Value, String> {
+    let (start, end) = match args.len() {
+        0 => return Err("range() expects at least one argument".to_string()),
+        1 => (0, args[0].to_int()),
+        _ => (args[0].to_int(), args[1].to_int()),
+    };
+    let step = args.get(2).map(|v| v.to_int()).unwrap_or(1);
+    if step == 0 {
+        return Err("range() step cannot be zero".to_string());
+    }
+
+    let span = end as i128 - start as i128;
+    let count = if span != 0 && (span > 0) == (step > 0) {
+        (span.abs() + step.unsigned_abs() as i128 - 1) / step.unsigned_abs() as i128
+    } else {
+        0
+    };
+    if count > MAX_RANGE {
+        return Err(format!(
+            "range() would make {} elements, more than {}",
+            count, MAX_RANGE
+        ));
+    }
+
+    let mut values = Vec::with_capacity(count as usize);
+    let mut current = Some(start);
+    while let Some(n) = current.filter(|&n| (step > 0 && n < end) || (step < 0 && n > end)) {
+        values.push(Value::Int(n));
+        current = n.checked_add(step);
+    }
+    Ok(Value::Array(values))
+}
+
+fn sum(arr: &[Value]) -> Result<Value, String> {
+    let mut total: i64 = 0;
+    for (idx, item) in arr.iter().enumerate() {
+        match item {
+            Value::Int(n) => {
+                total = total
+                    .checked_add(*n)
+                    .ok_or("sum() overflowed the integer range")?
+            }
+            other => {
+                return Err(format!(
+                    "sum() expects an array of numbers, got {} '{}' at index {}",
+                    other.type_name(),
+                    other,
+                    idx
+                ))
+            }
+        }
+    }
+    Ok(Value::Int(total))
+}
+
+/// `min`/`max` accept either a single array or several values.
+fn extreme(args: &[Value], wanted: Ordering) -> Value {
+    let items: &[Value] = match args {
+        [Value::Array(arr)] => arr,
+        _ => args,
+    };
+
+    let mut best: Option<&Value> = None;
+    for item in items {
+        best = match best {
+            Some(current) if item.compare(current) != Some(wanted) => Some(current),
+            _ => Some(item),
+        };
+    }
+    best.cloned().unwrap_or(Value::Nil)
+}
@@ -0,0 +1,397 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::value::Value;
+
+const DUMP_WIDTH: usize = 16;
+
+/// Largest buffer `bytes(n)` or a `pack` format may ask for.
+const MAX_SIZE: usize = 1 << 28;
+
+pub fn call(name: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+    let result = match name {
+        "bytes" => from_value(args.first().unwrap_or(&Value::Nil)).map(Value::Bytes),
+        "encode" => encode(args),
+        "decode" => decode(args),
+        "hex" => expect_bytes(name, args).map(|bytes| Value::String(to_hex(bytes))),
+        "unhex" => {
+            from_hex(&args.first().map(|v| v.to_string()).unwrap_or_default()).map(Value::Bytes)
+        }
+        "hexdump" => expect_bytes(name, args).map(|bytes| Value::String(hexdump(bytes))),
+        "pack" => pack(args),
+        "unpack" => unpack(args),
+        "packsize" => parse_format(&args.first().map(|v| v.to_string()).unwrap_or_default())
+            .map(|(_, _, size)| Value::Int(size as i64)),
+        _ => return None,
+    };
+    Some(result.map_err(bytes_error))
+}
+
+fn bytes_error(message: String) -> Error {
+    Error::new("bytes", message)
+}
+
+fn expect_bytes<'a>(name: &str, args: &'a [Value]) -> Result<&'a [u8], String> {
+    match args.first() {
+        Some(Value::Bytes(bytes)) => Ok(bytes),
+        Some(other) => Err(format!("{}() expects bytes, got '{}'", name, other)),
+        None => Err(format!("{}() expects a bytes argument", name)),
+    }
+}
+
+pub fn to_byte(value: &Value) -> Result<u8, String> {
+    match value {
+        Value::Int(n) => {
+            u8::try_from(*n).map_err(|_| format!("Byte value {} is out of range 0-255", n))
+        }
+        other => Err(format!("Expected a byte value 0-255, got '{}'", other)),
+    }
+}
+
+fn from_value(value: &Value) -> Result<Vec<u8>, String> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes.clone()),
+        Value::String(s) => Ok(s.as_bytes().to_vec()),
+        Value::Array(items) => items.iter().map(to_byte).collect(),
+        Value::Int(n) if *n >= 0 => match usize::try_from(*n) {
+            Ok(n) if n <= MAX_SIZE => Ok(vec![0; n]),
+            _ => Err(format!(
+                "bytes() cannot make {} bytes, the limit is {}",
+                n, MAX_SIZE
+            )),
+        },
+        Value::Nil => Ok(Vec::new()),
+        other => Err(format!("bytes() cannot convert '{}'", other)),
+    }
+}
+
+fn encoding_arg(args: &[Value]) -> String {
+    args.get(1)
+        .map(|v| v.to_string().to_lowercase().replace('_', "-"))
+        .unwrap_or_else(|| "utf-8".to_string())
+}
+
+fn encode(args: &[Value]) -> Result<Value, String> {
+    let text = args.first().map(|v| v.to_string()).unwrap_or_default();
+    let encoding = encoding_arg(args);
+    let bytes = match encoding.as_str() {
+        "utf-8" | "utf8" => text.into_bytes(),
+        "latin1" | "latin-1" | "iso-8859-1" => text
+            .chars()
+            .map(|c| {
+                u8::try_from(c as u32)
+                    .map_err(|_| format!("Character '{}' cannot be encoded as latin1", c))
+            })
+            .collect::<Result<_, _>>()?,
+        "ascii" | "us-ascii" => {
+            if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+                return Err(format!("Character '{}' cannot be encoded as ascii", c));
+            }
+            text.into_bytes()
+        }
+        "utf-16le" => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        "utf-16be" => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        other => return Err(format!("Unknown encoding '{}'", other)),
+    };
+    Ok(Value::Bytes(bytes))
+}
+
+fn decode(args: &[Value]) -> Result<Value, String> {
+    let bytes = expect_bytes("decode", args)?;
+    let encoding = encoding_arg(args);
+    let text = match encoding.as_str() {
+        "utf-8" | "utf8" => String::from_utf8(bytes.to_vec())
+            .map_err(|e| format!("Invalid utf-8 at byte {}", e.utf8_error().valid_up_to()))?,
+        "utf-8-lossy" | "utf8-lossy" => String::from_utf8_lossy(bytes).to_string(),
+        "latin1" | "latin-1" | "iso-8859-1" => bytes.iter().map(|&b| b as char).collect(),
+        "ascii" | "us-ascii" => match bytes.iter().position(|b| !b.is_ascii()) {
+            Some(pos) => return Err(format!("Invalid ascii at byte {}", pos)),
+            None => String::from_utf8_lossy(bytes).to_string(),
+        },
+        "utf-16le" | "utf-16be" => {
+            if !bytes.len().is_multiple_of(2) {
+                return Err(format!("Odd number of bytes for {}", encoding));
+            }
+            let units: Vec<u16> = bytes
+                .chunks(2)
+                .map(|pair| match encoding.as_str() {
+                    "utf-16le" => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+            String::from_utf16(&units).map_err(|_| format!("Invalid {}", encoding))?
+        }
+        other => return Err(format!("Unknown encoding '{}'", other)),
+    };
+    Ok(Value::String(text))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("unhex() expects an even number of hex digits".to_string());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("Invalid hex digits '{}'", pair))
+        })
+        .collect()
+}
+
+fn hexdump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes.chunks(DUMP_WIDTH).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if (0x20..0x7f).contains(&b) {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "{:08x}  {:<width$}  |{}|\n",
+            line * DUMP_WIDTH,
+            hex.join(" "),
+            ascii,
+            width = DUMP_WIDTH * 3 - 1
+        ));
+    }
+    out
+}
+
+/// For `s`/`a` the count is the field width in bytes.
+struct Field {
+    code: char,
+    count: usize,
+}
+
+impl Field {
+    fn integer(&self) -> Option<(usize, bool)> {
+        match self.code {
+            'b' => Some((1, true)),
+            'B' => Some((1, false)),
+            'h' => Some((2, true)),
+            'H' => Some((2, false)),
+            'i' | 'l' => Some((4, true)),
+            'I' | 'L' => Some((4, false)),
+            'q' => Some((8, true)),
+            'Q' => Some((8, false)),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> Option<usize> {
+        match self.integer() {
+            Some((width, _)) => width.checked_mul(self.count),
+            None => Some(self.count),
+        }
+    }
+}
+
+/// Returns the byte order, the fields and the bytes they cover.
+fn parse_format(format: &str) -> Result<(bool, Vec<Field>, usize), String> {
+    let mut chars = format.chars().filter(|c| !c.is_whitespace()).peekable();
+    let big_endian = match chars.peek() {
+        Some('<') => {
+            chars.next();
+            false
+        }
+        Some('>' | '!') => {
+            chars.next();
+            true
+        }
+        Some('=' | '@') => {
+            chars.next();
+            cfg!(target_endian = "big")
+        }
+        _ => cfg!(target_endian = "big"),
+    };
+    let fields = parse_fields(chars)?;
+
+    let size = fields
+        .iter()
+        .try_fold(0usize, |total, field| total.checked_add(field.size()?))
+        .filter(|&size| size <= MAX_SIZE)
+        .ok_or_else(|| format!("Format '{}' covers more than {} bytes", format, MAX_SIZE))?;
+    Ok((big_endian, fields, size))
+}
+
+fn parse_fields(
+    mut chars: std::iter::Peekable<impl Iterator<Item = char>>,
+) -> Result<Vec<Field>, String> {
+    let mut fields = Vec::new();
+    while let Some(c) = chars.next() {
+        let mut digits = String::new();
+        let mut code = c;
+        if c.is_ascii_digit() {
+            digits.push(c);
+            while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                digits.push(d);
+            }
+            code = chars
+                .next()
+                .ok_or_else(|| format!("Format ends after the count {}", digits))?;
+        }
+        let count = if digits.is_empty() {
+            1
+        } else {
+            digits
+                .parse()
+                .map_err(|_| format!("Invalid count {}", digits))?
+        };
+
+        let field = Field { code, count };
+        if field.integer().is_none() && !matches!(code, 's' | 'a' | 'x') {
+            return Err(format!("Unknown pack format code '{}'", code));
+        }
+        fields.push(field);
+    }
+    Ok(fields)
+}
+
+fn pack(args: &[Value]) -> Result<Value, String> {
+    let format = args.first().map(|v| v.to_string()).unwrap_or_default();
+    let (big_endian, fields, _) = parse_format(&format)?;
+    let mut values = args.iter().skip(1);
+    let mut next = |code: char| {
+        values
+            .next()
+            .ok_or_else(|| format!("pack() needs more values for '{}' in '{}'", code, format))
+    };
+
+    let mut out = Vec::new();
+    for field in &fields {
+        match (field.code, field.integer()) {
+            ('x', _) => out.resize(out.len() + field.count, 0),
+            ('s' | 'a', _) => {
+                // Fixed width: truncated or padded with NUL bytes.
+                let mut data = next(field.code)?.to_bytes();
+                data.resize(field.count, 0);
+                out.extend_from_slice(&data);
+            }
+            (code, Some((width, signed))) => {
+                for _ in 0..field.count {
+                    let n = match next(code)? {
+                        Value::Int(n) => *n,
+                        Value::Bool(b) => *b as i64,
+                        other => {
+                            return Err(format!(
+                                "pack() expects an integer for '{}', got '{}'",
+                                code, other
+                            ))
+                        }
+                    };
+                    let bits = (width * 8) as u32;
+                    let fits = if signed {
+                        bits == 64 || (n >= -(1 << (bits - 1)) && n < (1 << (bits - 1)))
+                    } else {
+                        n >= 0 && (bits == 64 || n < (1 << bits))
+                    };
+                    if !fits {
+                        return Err(format!("Value {} does not fit in format '{}'", n, code));
+                    }
+                    let raw = n.to_be_bytes();
+                    let mut bytes = raw[8 - width..].to_vec();
+                    if !big_endian {
+                        bytes.reverse();
+                    }
+                    out.extend_from_slice(&bytes);
+                }
+            }
+            _ => unreachable!("codes are validated by parse_format"),
+        }
+    }
+
+    if args.len() > 1 + fields_values(&fields) {
+        return Err(format!(
+            "pack() got {} values but '{}' takes {}",
+            args.len() - 1,
+            format,
+            fields_values(&fields)
+        ));
+    }
+    Ok(Value::Bytes(out))
+}
+
+fn fields_values(fields: &[Field]) -> usize {
+    fields
+        .iter()
+        .map(|field| match field.code {
+            'x' => 0,
+            's' | 'a' => 1,
+            _ => field.count,
+        })
+        .sum()
+}
+
+/// Bytes past the end of the format are ignored.
+fn unpack(args: &[Value]) -> Result<Value, String> {
+    let format = args.first().map(|v| v.to_string()).unwrap_or_default();
+    let data = args.get(1).map(|v| v.to_bytes()).unwrap_or_default();
+    let (big_endian, fields, needed) = parse_format(&format)?;
+    if data.len() < needed {
+        return Err(format!(
+            "unpack() needs {} bytes for '{}', got {}",
+            needed,
+            format,
+            data.len()
+        ));
+    }
+
+    let mut values = Vec::new();
+    let mut pos = 0;
+    for field in &fields {
+        match (field.code, field.integer()) {
+            ('x', _) => pos += field.count,
+            ('s', _) => {
+                // Text field: the NUL padding `pack` added is dropped.
+                let raw = &data[pos..pos + field.count];
+                let end = raw.iter().rposition(|&b| b != 0).map_or(0, |idx| idx + 1);
+                values.push(Value::String(
+                    String::from_utf8_lossy(&raw[..end]).to_string(),
+                ));
+                pos += field.count;
+            }
+            ('a', _) => {
+                values.push(Value::Bytes(data[pos..pos + field.count].to_vec()));
+                pos += field.count;
+            }
+            (code, Some((width, signed))) => {
+                for _ in 0..field.count {
+                    let mut raw = data[pos..pos + width].to_vec();
+                    if !big_endian {
+                        raw.reverse();
+                    }
+                    let negative = signed && raw[0] & 0x80 != 0;
+                    let mut buf = if negative { [0xff; 8] } else { [0; 8] };
+                    buf[8 - width..].copy_from_slice(&raw);
+                    let n = i64::from_be_bytes(buf);
+                    if !signed && n < 0 {
+                        return Err(format!(
+                            "Unsigned value for '{}' is too large for an integer",
+                            code
+                        ));
+                    }
+                    values.push(Value::Int(n));
+                    pos += width;
+                }
+            }
+            _ => unreachable!("codes are validated by parse_format"),
+        }
+    }
+    Ok(Value::Array(values))
+}
@@ -0,0 +1,288 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::files::file_error;
+use crate::value::{map_get, map_insert, Map, Value};
+use std::fs;
+use std::path::PathBuf;
+
+/// `resolve` maps script paths the same way the file built-ins do.
+pub fn call<F>(name: &str, args: &[Value], resolve: F) -> Option<Result<Value, Error>>
+where
+    F: Fn(&str) -> PathBuf,
+{
+    let arg = |idx: usize| args.get(idx).unwrap_or(&Value::Nil);
+    let path = || resolve(&arg(0).to_string());
+
+    let result = match name {
+        "csv_parse" => {
+            Options::from_value(arg(1)).and_then(|options| parse(&arg(0).to_string(), &options))
+        }
+        "csv_format" => Options::from_value(arg(1))
+            .and_then(|options| format(arg(0), &options))
+            .map(Value::String),
+        "csv_read" => Options::from_value(arg(1)).and_then(|options| {
+            let path = path();
+            let data = fs::read(&path).map_err(|e| file_error("read", &path, e))?;
+            parse(&String::from_utf8_lossy(&data), &options)
+        }),
+        "csv_write" => Options::from_value(arg(2)).and_then(|options| {
+            let path = path();
+            let text = format(arg(1), &options)?;
+            fs::write(&path, text)
+                .map(|_| Value::Bool(true))
+                .map_err(|e| file_error("write", &path, e))
+        }),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn csv_error(message: impl Into<String>) -> Error {
+    Error::new("csv", message)
+}
+
+struct Options {
+    delimiter: char,
+    header: Option<bool>,
+    crlf: bool,
+}
+
+impl Options {
+    fn from_value(options: &Value) -> Result<Self, Error> {
+        let options = match options {
+            Value::Map(map) => map.as_slice(),
+            Value::Nil => &[],
+            other => {
+                return Err(csv_error(format!(
+                    "CSV options must be a map, got '{}'",
+                    other
+                )))
+            }
+        };
+        let option = |key: &str| map_get(options, key);
+
+        let delimiter = match option("delimiter") {
+            None | Some(Value::Nil) => ',',
+            Some(value) => {
+                let text = value.to_string();
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c != '"' && c != '\r' && c != '\n' => c,
+                    _ => {
+                        return Err(csv_error(format!(
+                            "CSV delimiter must be a single character other than a quote or newline, got '{}'",
+                            text
+                        )))
+                    }
+                }
+            }
+        };
+
+        Ok(Options {
+            delimiter,
+            header: option("header").map(|v| v.is_truthy()),
+            crlf: option("crlf").is_some_and(|v| v.is_truthy()),
+        })
+    }
+}
+
+/// Blank lines are skipped and a final line ending is optional.
+fn parse(text: &str, options: &Options) -> Result<Value, Error> {
+    let records = records(text, options.delimiter)?;
+    if !options.header.unwrap_or(false) {
+        return Ok(Value::Array(
+            records
+                .into_iter()
+                .map(|(_, fields)| Value::Array(fields.into_iter().map(Value::String).collect()))
+                .collect(),
+        ));
+    }
+
+    let mut records = records.into_iter();
+    let names = match records.next() {
+        Some((_, names)) => names,
+        None => return Ok(Value::Array(Vec::new())),
+    };
+    let mut rows = Vec::new();
+    for (line, fields) in records {
+        if fields.len() > names.len() {
+            return Err(csv_error(format!(
+                "Record on line {} has {} fields but the header has {}",
+                line,
+                fields.len(),
+                names.len()
+            )));
+        }
+        // Short records leave the remaining fields empty.
+        let mut fields = fields.into_iter();
+        let mut row = Map::new();
+        for name in &names {
+            let field = fields.next().unwrap_or_default();
+            map_insert(&mut row, name.clone(), Value::String(field));
+        }
+        rows.push(Value::Map(row));
+    }
+    Ok(Value::Array(rows))
+}
+
+fn records(text: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, Error> {
+    let mut records = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        // Whether the current field is a quoted one whose closing quote
+        // has been seen; only a delimiter or line ending may follow it.
+        let mut closed = false;
+        let mut blank = true;
+
+        loop {
+            match chars.next() {
+                None => break,
+                Some('\n') => {
+                    line += 1;
+                    break;
+                }
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                    line += 1;
+                    break;
+                }
+                Some(c) if c == delimiter => {
+                    fields.push(std::mem::take(&mut field));
+                    closed = false;
+                    blank = false;
+                }
+                Some(_) if closed => {
+                    return Err(csv_error(format!(
+                        "Unexpected character after closing quote on line {}",
+                        line
+                    )))
+                }
+                Some('"') if field.is_empty() => {
+                    let opened = line;
+                    loop {
+                        match chars.next() {
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                field.push('"');
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                field.push(c);
+                            }
+                            None => {
+                                return Err(csv_error(format!(
+                                    "Unterminated quoted field starting on line {}",
+                                    opened
+                                )))
+                            }
+                        }
+                    }
+                    closed = true;
+                    blank = false;
+                }
+                // A quote inside an unquoted field is kept as it is.
+                Some(c) => {
+                    field.push(c);
+                    blank = false;
+                }
+            }
+        }
+
+        if !blank {
+            fields.push(field);
+            records.push((start, fields));
+        }
+    }
+    Ok(records)
+}
+
+/// Map rows use every key in the order first seen as the columns.
+fn format(rows: &Value, options: &Options) -> Result<String, Error> {
+    let rows = match rows {
+        Value::Array(rows) => rows,
+        other => {
+            return Err(csv_error(format!(
+                "csv_format expects an array of rows, got '{}'",
+                other
+            )))
+        }
+    };
+    let ending = if options.crlf { "\r\n" } else { "\n" };
+    let mut out = String::new();
+
+    let mut columns: Vec<&str> = Vec::new();
+    for row in rows {
+        if let Value::Map(map) = row {
+            for (key, _) in map {
+                if !columns.contains(&key.as_str()) {
+                    columns.push(key);
+                }
+            }
+        }
+    }
+    if !columns.is_empty() && options.header.unwrap_or(true) {
+        let names: Vec<Value> = columns.iter().map(|&c| Value::String(c.into())).collect();
+        write_record(&names, options.delimiter, &mut out)?;
+        out.push_str(ending);
+    }
+
+    for (idx, row) in rows.iter().enumerate() {
+        let fields: Vec<Value> = match row {
+            Value::Array(fields) => fields.clone(),
+            Value::Map(map) => columns
+                .iter()
+                .map(|&column| map_get(map, column).cloned().unwrap_or(Value::Nil))
+                .collect(),
+            other => {
+                return Err(csv_error(format!(
+                    "Row {} must be an array or a map, got '{}'",
+                    idx, other
+                )))
+            }
+        };
+        write_record(&fields, options.delimiter, &mut out)?;
+        out.push_str(ending);
+    }
+    Ok(out)
+}
+
+fn write_record(fields: &[Value], delimiter: char, out: &mut String) -> Result<(), Error> {
+    for (idx, field) in fields.iter().enumerate() {
+        if idx > 0 {
+            out.push(delimiter);
+        }
+        let text = match field {
+            Value::Nil => String::new(),
+            Value::Bytes(bytes) => String::from_utf8_lossy(bytes).to_string(),
+            Value::Array(_) | Value::Map(_) | Value::Function(_) => {
+                return Err(csv_error(format!(
+                    "CSV fields must be plain values, got '{}'",
+                    field
+                )))
+            }
+            other => other.to_string(),
+        };
+        // Quote only when needed, doubling any quotes inside.
+        if text.contains([delimiter, '"', '\r', '\n']) {
+            out.push('"');
+            out.push_str(&text.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(&text);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::value::{map_get, map_insert, Map, Value};
+use std::fmt;
+
+/// A runtime error raised by the interpreter or by a script's `throw`.
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub kind: String,
+    pub message: String,
+    /// Source line the error surfaced at; 0 until a statement claims it.
+    pub line: usize,
+    /// The value passed to `throw`, when the error came from a script.
+    pub thrown: Option<Value>,
+}
+
+impl Error {
+    pub fn new(kind: &str, message: impl Into<String>) -> Self {
+        Error {
+            kind: kind.to_string(),
+            message: message.into(),
+            line: 0,
+            thrown: None,
+        }
+    }
+
+    /// Build the error for `throw value`. Maps may carry their own
+    /// `message` and `kind`; anything else becomes the message.
+    pub fn thrown(value: Value) -> Self {
+        let (kind, message) = match &value {
+            Value::Map(map) => (
+                map_get(map, "kind")
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "error".to_string()),
+                map_get(map, "message")
+                    .map(|v| v.to_string())
+                    .unwrap_or_default(),
+            ),
+            other => ("error".to_string(), other.to_string()),
+        };
+
+        Error {
+            kind,
+            message,
+            line: 0,
+            thrown: Some(value),
+        }
+    }
+
+    /// Record where the error happened unless an inner statement already did.
+    pub fn at_line(mut self, line: usize) -> Self {
+        if self.line == 0 {
+            self.line = line;
+        }
+        self
+    }
+
+    /// The value bound by `catch ($err)`: a map with `message`, `kind` and
+    /// `line`, plus any extra keys of a thrown map.
+    pub fn to_value(&self) -> Value {
+        let mut map: Map = match &self.thrown {
+            Some(Value::Map(thrown)) => thrown.clone(),
+            _ => Vec::new(),
+        };
+        map_insert(
+            &mut map,
+            "message".to_string(),
+            Value::String(self.message.clone()),
+        );
+        map_insert(
+            &mut map,
+            "kind".to_string(),
+            Value::String(self.kind.clone()),
+        );
+        map_insert(&mut map, "line".to_string(), Value::Int(self.line as i64));
+        Value::Map(map)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::new("runtime", message)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{} (line {})", self.message, self.line)
+        } else {
+            write!(f, "{}", self.message)
+        }
+    }
+}
@@ -0,0 +1,458 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::value::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// `resolve` maps script paths so relative ones behave as in `include`.
+pub fn call<F>(name: &str, args: &[Value], resolve: F) -> Option<Result<Value, Error>>
+where
+    F: Fn(&str) -> PathBuf,
+{
+    let path = || resolve(&args.first().map(|v| v.to_string()).unwrap_or_default());
+    let data = || args.get(1).map(|v| v.to_bytes()).unwrap_or_default();
+    let target = || resolve(&args.get(1).map(|v| v.to_string()).unwrap_or_default());
+    let flag = |idx: usize| args.get(idx).is_some_and(|v| v.is_truthy());
+
+    let result = match name {
+        "file_read" => read(&path()).map(|data| Value::String(text(&data))),
+        "file_readbytes" => read(&path()).map(Value::Bytes),
+        "file_lines" => read(&path()).map(|data| {
+            // A trailing newline does not produce an empty last line.
+            Value::Array(
+                text(&data)
+                    .lines()
+                    .map(|line| Value::String(line.to_string()))
+                    .collect(),
+            )
+        }),
+        "file_write" => {
+            let path = path();
+            fs::write(&path, data())
+                .map(|_| Value::Bool(true))
+                .map_err(|e| file_error("write", &path, e))
+        }
+        "file_append" => {
+            let path = path();
+            append(&path, &data())
+                .map(|_| Value::Bool(true))
+                .map_err(|e| file_error("append to", &path, e))
+        }
+        "file_exists" => Ok(Value::Bool(path().exists())),
+        "file_delete" => {
+            let path = path();
+            fs::remove_file(&path)
+                .map(|_| Value::Bool(true))
+                .map_err(|e| file_error("delete", &path, e))
+        }
+        "file_size" => {
+            let path = path();
+            fs::metadata(&path)
+                .map(|meta| Value::Int(meta.len() as i64))
+                .map_err(|e| file_error("stat", &path, e))
+        }
+        "listdir" => {
+            let path = path();
+            entries(&path)
+                .map(|names| Value::Array(names.into_iter().map(Value::String).collect()))
+                .map_err(|e| file_error("list", &path, e))
+        }
+        "walk" => {
+            let shown = args.first().map(|v| v.to_string()).unwrap_or_default();
+            let path = path();
+            let mut found = Vec::new();
+            walk(&path, &shown, &mut found)
+                .map(|_| Value::Array(found.into_iter().map(Value::String).collect()))
+                .map_err(|e| file_error("walk", &path, e))
+        }
+        "glob" => {
+            let pattern = args.first().map(|v| v.to_string()).unwrap_or_default();
+            Ok(Value::Array(
+                glob(&pattern, &resolve)
+                    .into_iter()
+                    .map(Value::String)
+                    .collect(),
+            ))
+        }
+        "mkdir" => {
+            let path = path();
+            let created = if flag(1) {
+                fs::create_dir_all(&path)
+            } else {
+                fs::create_dir(&path)
+            };
+            created
+                .map(|_| Value::Bool(true))
+                .map_err(|e| file_error("create directory", &path, e))
+        }
+        "rmdir" => {
+            let path = path();
+            let removed = if flag(1) {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_dir(&path)
+            };
+            removed
+                .map(|_| Value::Bool(true))
+                .map_err(|e| file_error("remove directory", &path, e))
+        }
+        "rename" => {
+            let path = path();
+            fs::rename(&path, target())
+                .map(|_| Value::Bool(true))
+                .map_err(|e| file_error("rename", &path, e))
+        }
+        "copy" => {
+            let path = path();
+            // Copying into a directory keeps the file name, like `cp`.
+            let mut target = target();
+            if target.is_dir() {
+                if let Some(name) = path.file_name() {
+                    target.push(name);
+                }
+            }
+            fs::copy(&path, &target)
+                .map(|_| Value::Bool(true))
+                .map_err(|e| file_error("copy", &path, e))
+        }
+        "stat" => {
+            let path = path();
+            fs::metadata(&path)
+                .map(|meta| Value::Map(stat(&meta)))
+                .map_err(|e| file_error("stat", &path, e))
+        }
+        "basename" => {
+            let path = args.first().map(|v| v.to_string()).unwrap_or_default();
+            Ok(Value::String(basename(&path)))
+        }
+        "dirname" => {
+            let path = args.first().map(|v| v.to_string()).unwrap_or_default();
+            Ok(Value::String(dirname(&path)))
+        }
+        "join_path" => {
+            let mut joined = PathBuf::new();
+            for part in args {
+                joined.push(part.to_string());
+            }
+            Ok(Value::String(joined.to_string_lossy().to_string()))
+        }
+        "abspath" => {
+            // Resolved the way the other file built-ins resolve, then made
+            // canonical like the include base directories when it exists.
+            let path = path();
+            let absolute = path.canonicalize().unwrap_or(path);
+            Ok(Value::String(absolute.to_string_lossy().to_string()))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+pub fn file_error(action: &str, path: &Path, error: io::Error) -> Error {
+    Error::new(
+        "file",
+        format!("Failed to {} '{}': {}", action, path.display(), error),
+    )
+}
+
+fn append(path: &Path, data: &[u8]) -> io::Result<()> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(data)
+}
+
+fn entries(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        names.push(entry?.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Symbolic links are listed but not followed.
+fn walk(dir: &Path, shown: &str, found: &mut Vec<String>) -> io::Result<()> {
+    for name in entries(dir)? {
+        let path = dir.join(&name);
+        let shown = join_shown(shown, &name);
+        found.push(shown.clone());
+        if fs::symlink_metadata(&path)?.is_dir() {
+            walk(&path, &shown, found)?;
+        }
+    }
+    Ok(())
+}
+
+fn join_shown(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn has_wildcard(part: &str) -> bool {
+    part.contains(['*', '?', '['])
+}
+
+/// Wildcards skip names starting with `.` unless the pattern spells the
+/// dot out.
+fn glob<F>(pattern: &str, resolve: &F) -> Vec<String>
+where
+    F: Fn(&str) -> PathBuf,
+{
+    let parts: Vec<&str> = pattern.split('/').collect();
+    let fixed = parts.iter().take_while(|part| !has_wildcard(part)).count();
+    if fixed == parts.len() {
+        if resolve(pattern).exists() {
+            return vec![pattern.to_string()];
+        }
+        return Vec::new();
+    }
+
+    let shown = match parts[..fixed].join("/") {
+        prefix if prefix.is_empty() && pattern.starts_with('/') => "/".to_string(),
+        prefix => prefix,
+    };
+    let root = resolve(if shown.is_empty() { "." } else { &shown });
+
+    let mut found = Vec::new();
+    glob_in(&root, &shown, &parts[fixed..], &mut found);
+    found.sort();
+    found.dedup();
+    found
+}
+
+fn glob_in(dir: &Path, shown: &str, parts: &[&str], found: &mut Vec<String>) {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => {
+            found.push(shown.to_string());
+            return;
+        }
+    };
+
+    if part.is_empty() {
+        // A doubled or trailing slash.
+        glob_in(dir, shown, rest, found);
+        return;
+    }
+    if !has_wildcard(part) {
+        let path = dir.join(part);
+        if path.exists() {
+            glob_in(&path, &join_shown(shown, part), rest, found);
+        }
+        return;
+    }
+
+    let names = match entries(dir) {
+        Ok(names) => names,
+        Err(_) => return,
+    };
+    if *part == "**" {
+        glob_in(dir, shown, rest, found);
+        for name in names.iter().filter(|name| !name.starts_with('.')) {
+            let path = dir.join(name);
+            if fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_dir()) {
+                glob_in(&path, &join_shown(shown, name), parts, found);
+            }
+        }
+        return;
+    }
+
+    let pattern: Vec<char> = part.chars().collect();
+    for name in names {
+        if name.starts_with('.') && !part.starts_with('.') {
+            continue;
+        }
+        let chars: Vec<char> = name.chars().collect();
+        if !wildcard_match(&pattern, &chars) {
+            continue;
+        }
+        let path = dir.join(&name);
+        if rest.is_empty() || path.is_dir() {
+            glob_in(&path, &join_shown(shown, &name), rest, found);
+        }
+    }
+}
+
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| wildcard_match(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && wildcard_match(&pattern[1..], &name[1..]),
+        Some('[') => match (name.first(), class_end(pattern)) {
+            (Some(&c), Some(end)) => {
+                class_matches(&pattern[1..end], c)
+                    && wildcard_match(&pattern[end + 1..], &name[1..])
+            }
+            // An unclosed `[` is an ordinary character.
+            (Some(&c), None) => c == '[' && wildcard_match(&pattern[1..], &name[1..]),
+            (None, _) => false,
+        },
+        Some(&literal) => {
+            name.first() == Some(&literal) && wildcard_match(&pattern[1..], &name[1..])
+        }
+    }
+}
+
+/// A `]` right after the `[` (or after `[!`) is part of the class.
+fn class_end(pattern: &[char]) -> Option<usize> {
+    let mut idx = 1;
+    if matches!(pattern.get(idx), Some('!' | '^')) {
+        idx += 1;
+    }
+    if pattern.get(idx) == Some(&']') {
+        idx += 1;
+    }
+    (idx..pattern.len()).find(|&i| pattern[i] == ']')
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some('!' | '^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    let mut idx = 0;
+    while idx < class.len() {
+        if idx + 2 < class.len() && class[idx + 1] == '-' {
+            matched |= (class[idx]..=class[idx + 2]).contains(&c);
+            idx += 3;
+        } else {
+            matched |= class[idx] == c;
+            idx += 1;
+        }
+    }
+    matched != negated
+}
+
+fn stat(meta: &fs::Metadata) -> Vec<(String, Value)> {
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|age| age.as_secs() as i64)
+        .unwrap_or(0);
+    let kind = if meta.is_dir() {
+        "dir"
+    } else if meta.is_file() {
+        "file"
+    } else {
+        "other"
+    };
+    vec![
+        ("size".to_string(), Value::Int(meta.len() as i64)),
+        ("mtime".to_string(), Value::Int(mtime)),
+        ("mode".to_string(), Value::Int(mode(meta))),
+        ("type".to_string(), Value::String(kind.to_string())),
+    ]
+}
+
+#[cfg(unix)]
+fn mode(meta: &fs::Metadata) -> i64 {
+    use std::os::unix::fs::PermissionsExt;
+    (meta.permissions().mode() & 0o7777) as i64
+}
+
+#[cfg(not(unix))]
+fn mode(meta: &fs::Metadata) -> i64 {
+    if meta.permissions().readonly() {
+        0o444
+    } else {
+        0o666
+    }
+}
+
+fn basename(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// As the `dirname` command does.
+fn dirname(path: &str) -> String {
+    match Path::new(path).parent() {
+        Some(parent) if parent.as_os_str().is_empty() => ".".to_string(),
+        Some(parent) => parent.to_string_lossy().to_string(),
+        None if path.starts_with('/') => "/".to_string(),
+        None => ".".to_string(),
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|e| file_error("read", path, e))
+}
+
+fn text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).to_string()
+}
+
+pub struct FileHandle {
+    reader: BufReader<File>,
+}
+
+impl FileHandle {
+    /// C-style mode: `r`, `w` or `a`, optionally with `+`; `b` is ignored.
+    pub fn open(path: &Path, mode: &str) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        match mode.replace('b', "").as_str() {
+            "r" => options.read(true),
+            "r+" => options.read(true).write(true),
+            "w" => options.write(true).create(true).truncate(true),
+            "w+" => options.read(true).write(true).create(true).truncate(true),
+            "a" => options.append(true).create(true),
+            "a+" => options.read(true).append(true).create(true),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid mode '{}'", mode),
+                ))
+            }
+        };
+        options.open(path).map(|file| FileHandle {
+            reader: BufReader::new(file),
+        })
+    }
+
+    pub fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        if self.reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        // The file position is ahead of the script's by whatever is still
+        // buffered; seeking to the script's position drops the buffer and
+        // lines them up.
+        if !self.reader.buffer().is_empty() {
+            let pos = self.reader.stream_position()?;
+            self.reader.seek(SeekFrom::Start(pos))?;
+        }
+        self.reader.get_mut().write_all(data)
+    }
+
+    pub fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.reader.seek(pos)
+    }
+}
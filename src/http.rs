@@ -0,0 +1,699 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::socket::{self, socket_error, Socket};
+use crate::tls::{self, TlsOptions};
+use crate::value::{map_get, map_insert, Map, Value};
+use std::io;
+use std::time::Duration;
+
+const MAX_REDIRECTS: usize = 10;
+
+/// Default for the client's `timeout` option.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default for the server's `max_body` option.
+pub const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Longest line accepted in a message head, and most header fields.
+const MAX_LINE: usize = 8192;
+const MAX_HEADERS: usize = 100;
+
+/// Error kinds `http_serve` answers with 413 and 431; scripts never see them.
+pub const TOO_LARGE: &str = "too_large";
+
+pub const HEAD_TOO_LARGE: &str = "head_too_large";
+
+pub fn call(name: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+    let arg = |idx: usize| args.get(idx).cloned().unwrap_or(Value::Nil);
+    let result = match name {
+        "http_get" => request("GET", &arg(0).to_string(), &arg(1), b"", &arg(2)),
+        "http_request" => request(
+            &arg(0).to_string().to_uppercase(),
+            &arg(1).to_string(),
+            &arg(2),
+            &body_bytes(&arg(3)),
+            &arg(4),
+        ),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn body_bytes(body: &Value) -> Vec<u8> {
+    match body {
+        Value::Nil => Vec::new(),
+        other => other.to_bytes(),
+    }
+}
+
+fn http_error(message: impl Into<String>) -> Error {
+    Error::new("http", message)
+}
+
+#[derive(Debug, Clone)]
+struct Url {
+    https: bool,
+    host: String,
+    port: u16,
+    /// Path plus query string, always starting with `/`.
+    target: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Url, Error> {
+        let (https, rest) = match url.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => (false, rest),
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") => (true, rest),
+            Some((scheme, _)) => {
+                return Err(http_error(format!(
+                    "Unsupported URL scheme '{}' in '{}'",
+                    scheme, url
+                )))
+            }
+            None => return Err(http_error(format!("Invalid URL '{}'", url))),
+        };
+
+        let (authority, target) = match rest.find(['/', '?']) {
+            Some(idx) if rest[idx..].starts_with('?') => {
+                (&rest[..idx], format!("/{}", &rest[idx..]))
+            }
+            Some(idx) => (&rest[..idx], rest[idx..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        // Fragments are never sent to the server.
+        let target = match target.split_once('#') {
+            Some((before, _)) => before.to_string(),
+            None => target,
+        };
+
+        // An IPv6 host keeps its brackets, as in `[::1]:8080`.
+        let port_sep = match authority.rfind(']') {
+            Some(end) => authority[end..].find(':').map(|idx| end + idx),
+            None => authority.rfind(':'),
+        };
+        let (host, port) = match port_sep {
+            Some(idx) => {
+                let port = authority[idx + 1..]
+                    .parse()
+                    .map_err(|_| http_error(format!("Invalid port in URL '{}'", url)))?;
+                (&authority[..idx], port)
+            }
+            None => (authority, if https { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(http_error(format!("Missing host in URL '{}'", url)));
+        }
+
+        Ok(Url {
+            https,
+            host: host.to_string(),
+            port,
+            target,
+        })
+    }
+
+    fn scheme(&self) -> &'static str {
+        if self.https {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// The `Host` header omits the default port.
+    fn host_header(&self) -> String {
+        if self.port == if self.https { 443 } else { 80 } {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    fn to_url(&self) -> String {
+        format!("{}://{}{}", self.scheme(), self.host_header(), self.target)
+    }
+
+    fn join(&self, location: &str) -> Result<Url, Error> {
+        if location.contains("://") {
+            return Url::parse(location);
+        }
+        if let Some(rest) = location.strip_prefix("//") {
+            return Url::parse(&format!("{}://{}", self.scheme(), rest));
+        }
+
+        let target = if location.starts_with('/') {
+            location.to_string()
+        } else {
+            let path = self.target.split('?').next().unwrap_or("/");
+            let dir = &path[..path.rfind('/').map(|idx| idx + 1).unwrap_or(1)];
+            format!("{}{}", dir, location)
+        };
+        Ok(Url {
+            target,
+            ..self.clone()
+        })
+    }
+}
+
+struct Response {
+    status: i64,
+    reason: String,
+    headers: Map,
+    body: Vec<u8>,
+}
+
+/// Milliseconds; `0` removes the limit.
+fn timeout_option(options: &Value) -> Result<Option<Duration>, Error> {
+    let options = match options {
+        Value::Map(map) => map.as_slice(),
+        Value::Nil => &[],
+        other => {
+            return Err(http_error(format!(
+                "HTTP options must be a map, got '{}'",
+                other
+            )))
+        }
+    };
+    Ok(match map_get(options, "timeout") {
+        None | Some(Value::Nil) => Some(CLIENT_TIMEOUT),
+        Some(ms) => {
+            let ms = ms.to_int();
+            (ms > 0).then(|| Duration::from_millis(ms as u64))
+        }
+    })
+}
+
+/// Follows redirects and returns a map with `status`, `reason`, `headers`,
+/// `body` and the final `url`.
+pub fn request(
+    method: &str,
+    url: &str,
+    headers: &Value,
+    body: &[u8],
+    options: &Value,
+) -> Result<Value, Error> {
+    let mut extra = match headers {
+        Value::Map(map) => map.clone(),
+        Value::Nil => Map::new(),
+        other => {
+            return Err(http_error(format!(
+                "HTTP headers must be a map, got '{}'",
+                other
+            )))
+        }
+    };
+
+    let timeout = timeout_option(options)?;
+
+    let mut url = Url::parse(url)?;
+    let mut method = method.to_string();
+    let mut body = body.to_vec();
+
+    for _ in 0..=MAX_REDIRECTS {
+        let response =
+            send(&method, &url, &extra, &body, timeout).map_err(|e| match e.kind.as_str() {
+                HEAD_TOO_LARGE => http_error(e.message),
+                _ => e,
+            })?;
+
+        let location = map_get(&response.headers, "location").map(|v| v.to_string());
+        match (response.status, location) {
+            (301..=303 | 307 | 308, Some(location)) => {
+                // 303 always switches to GET; browsers do the same for a
+                // POST answered with 301 or 302.
+                if response.status == 303 || (response.status < 303 && method == "POST") {
+                    method = "GET".to_string();
+                    body.clear();
+                }
+                let next = url.join(&location)?;
+                // Credentials are only for the origin they were given for.
+                if (next.https, &next.host, next.port) != (url.https, &url.host, url.port) {
+                    extra.retain(|(name, _)| {
+                        !name.eq_ignore_ascii_case("authorization")
+                            && !name.eq_ignore_ascii_case("cookie")
+                    });
+                }
+                url = next;
+            }
+            _ => return Ok(to_value(response, &url)),
+        }
+    }
+
+    Err(http_error(format!(
+        "Too many redirects (more than {})",
+        MAX_REDIRECTS
+    )))
+}
+
+fn to_value(response: Response, url: &Url) -> Value {
+    Value::Map(vec![
+        ("status".to_string(), Value::Int(response.status)),
+        ("reason".to_string(), Value::String(response.reason)),
+        ("headers".to_string(), Value::Map(response.headers)),
+        (
+            "body".to_string(),
+            Value::String(String::from_utf8_lossy(&response.body).to_string()),
+        ),
+        ("url".to_string(), Value::String(url.to_url())),
+    ])
+}
+
+fn send(
+    method: &str,
+    url: &Url,
+    extra: &Map,
+    body: &[u8],
+    timeout: Option<Duration>,
+) -> Result<Response, Error> {
+    let addr = format!("{}:{}", url.host, url.port);
+    let mut socket = if url.https {
+        tls::connect(
+            &addr,
+            &TlsOptions::from_value(&url.host, &Value::Nil)?,
+            timeout,
+        )?
+    } else {
+        socket::connect(&addr, timeout)
+            .map_err(|e| socket_error(format!("Failed to connect to {}", addr), e))?
+    };
+    socket
+        .set_timeout(timeout)
+        .map_err(|e| socket_error(format!("Failed to set timeout on {}", addr), e))?;
+
+    // Defaults first, so a header passed by the script replaces them.
+    let mut headers: Map = vec![
+        ("Host".to_string(), Value::String(url.host_header())),
+        (
+            "User-Agent".to_string(),
+            Value::String(format!("minilux/{}", env!("CARGO_PKG_VERSION"))),
+        ),
+        ("Accept".to_string(), Value::String("*/*".to_string())),
+        ("Connection".to_string(), Value::String("close".to_string())),
+    ];
+    if !body.is_empty() || matches!(method, "POST" | "PUT" | "PATCH") {
+        headers.push((
+            "Content-Length".to_string(),
+            Value::String(body.len().to_string()),
+        ));
+    }
+    for (name, value) in extra {
+        headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+        headers.push((name.clone(), value.clone()));
+    }
+
+    if method.contains(['\r', '\n', ' ']) || url.target.contains(['\r', '\n', ' ']) {
+        return Err(http_error(format!(
+            "Invalid method or path in '{} {}'",
+            method.escape_debug(),
+            url.target.escape_debug()
+        )));
+    }
+    let mut head = format!("{} {} HTTP/1.1\r\n", method, url.target);
+    for (name, value) in &headers {
+        head.push_str(&header_line(name, value)?);
+    }
+    head.push_str("\r\n");
+
+    let mut payload = head.into_bytes();
+    payload.extend_from_slice(body);
+    socket
+        .send(&payload)
+        .map_err(|e| socket_error(format!("Failed to send request to {}", addr), e))?;
+
+    let (status_line, headers) = read_head(&mut socket)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or_default();
+    let status: i64 = parts
+        .next()
+        .and_then(|code| code.parse().ok())
+        .filter(|_| version.starts_with("HTTP/"))
+        .ok_or_else(|| http_error(format!("Malformed status line '{}'", status_line)))?;
+    let reason = parts.next().unwrap_or_default().to_string();
+
+    let body = if method == "HEAD" || (100..200).contains(&status) || status == 204 || status == 304
+    {
+        Vec::new()
+    } else {
+        read_body(&mut socket, &headers, true, None)?
+    };
+
+    Ok(Response {
+        status,
+        reason,
+        headers,
+        body,
+    })
+}
+
+/// Line breaks would let a value start a header of its own.
+fn header_line(name: &str, value: &Value) -> Result<String, Error> {
+    let value = value.to_string();
+    if name.is_empty() || name.contains([':', '\r', '\n']) || value.contains(['\r', '\n']) {
+        return Err(http_error(format!(
+            "Invalid header '{}: {}'",
+            name.escape_debug(),
+            value.escape_debug()
+        )));
+    }
+    Ok(format!("{}: {}\r\n", name, value))
+}
+
+fn read_line(socket: &mut Socket) -> Result<Option<String>, Error> {
+    socket
+        .read_line_max(MAX_LINE)
+        .map(|line| line.map(|bytes| String::from_utf8_lossy(&bytes).to_string()))
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => Error::new(HEAD_TOO_LARGE, format!("HTTP {}", e)),
+            _ => socket_error("Failed to read HTTP message".to_string(), e),
+        })
+}
+
+/// Header names are lowercased; repeated headers are joined with `, `.
+pub fn read_head(socket: &mut Socket) -> Result<(String, Map), Error> {
+    let start = read_line(socket)?
+        .ok_or_else(|| http_error("Connection closed before an HTTP message arrived"))?;
+
+    let mut headers = Map::new();
+    let mut fields = 0;
+    loop {
+        let line = read_line(socket)?
+            .ok_or_else(|| http_error("Connection closed in the middle of the headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        fields += 1;
+        if fields > MAX_HEADERS {
+            return Err(Error::new(
+                HEAD_TOO_LARGE,
+                format!("More than {} header fields", MAX_HEADERS),
+            ));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| http_error(format!("Malformed header line '{}'", line)))?;
+        let name = name.trim().to_lowercase();
+        let value = value.trim().to_string();
+        let joined = match map_get(&headers, &name) {
+            Some(existing) => format!("{}, {}", existing, value),
+            None => value,
+        };
+        map_insert(&mut headers, name, Value::String(joined));
+    }
+    Ok((start, headers))
+}
+
+fn too_large(message: impl Into<String>) -> Error {
+    Error::new(TOO_LARGE, message)
+}
+
+/// Without chunking or `Content-Length`, a response runs until the
+/// connection closes (`until_close`) while a request has no body.
+pub fn read_body(
+    socket: &mut Socket,
+    headers: &Map,
+    until_close: bool,
+    limit: Option<usize>,
+) -> Result<Vec<u8>, Error> {
+    let read_error = |e| socket_error("Failed to read HTTP body".to_string(), e);
+
+    let chunked = map_get(headers, "transfer-encoding")
+        .map(|v| v.to_string().to_lowercase().contains("chunked"))
+        .unwrap_or(false);
+    if chunked {
+        return read_chunked(socket, limit);
+    }
+
+    if let Some(length) = map_get(headers, "content-length") {
+        let length: usize = length
+            .to_string()
+            .trim()
+            .parse()
+            .map_err(|_| http_error(format!("Invalid Content-Length '{}'", length)))?;
+        if let Some(limit) = limit.filter(|&limit| length > limit) {
+            return Err(too_large(format!(
+                "Body of {} bytes is over the limit of {}",
+                length, limit
+            )));
+        }
+        return socket.read_exact(length).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                http_error(format!("Body ended early: {}", e))
+            } else {
+                read_error(e)
+            }
+        });
+    }
+
+    if until_close {
+        socket.read_to_end().map_err(read_error)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+fn read_chunked(socket: &mut Socket, limit: Option<usize>) -> Result<Vec<u8>, Error> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(socket)?
+            .ok_or_else(|| http_error("Connection closed in the middle of a chunked body"))?;
+        let size_text = line.split(';').next().unwrap_or_default().trim();
+        let size = Some(size_text)
+            .filter(|text| !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|text| usize::from_str_radix(text, 16).ok())
+            .ok_or_else(|| http_error(format!("Invalid chunk size '{}'", line)))?;
+        if let Some(limit) = limit {
+            if size > MAX_CHUNK_SIZE {
+                return Err(too_large(format!(
+                    "Chunk of {} bytes is over the limit of {}",
+                    size, MAX_CHUNK_SIZE
+                )));
+            }
+            if body.len() + size > limit {
+                return Err(too_large(format!(
+                    "Chunked body is over the limit of {} bytes",
+                    limit
+                )));
+            }
+        }
+
+        if size == 0 {
+            // Skip any trailer fields up to the final blank line.
+            while let Some(trailer) = read_line(socket)? {
+                if trailer.is_empty() {
+                    break;
+                }
+            }
+            return Ok(body);
+        }
+
+        let chunk = socket.read_exact(size).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                http_error("Connection closed in the middle of a chunk")
+            } else {
+                socket_error("Failed to read HTTP body".to_string(), e)
+            }
+        })?;
+        body.extend_from_slice(&chunk);
+        // The CRLF that ends each chunk.
+        read_line(socket)?;
+    }
+}
+
+pub fn percent_decode(text: &str, plus_as_space: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'%' if idx + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[idx + 1..idx + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        idx += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+pub fn parse_query(query: &str) -> Map {
+    let mut params = Map::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        map_insert(
+            &mut params,
+            percent_decode(key, true),
+            Value::String(percent_decode(value, true)),
+        );
+    }
+    params
+}
+
+pub fn read_request(socket: &mut Socket, max_body: usize) -> Result<Value, Error> {
+    let (request_line, headers) = read_head(socket)?;
+
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    let (method, target) = match parts.as_slice() {
+        [method, target, version] if version.starts_with("HTTP/1.") => (*method, *target),
+        _ => {
+            return Err(http_error(format!(
+                "Malformed request line '{}'",
+                request_line
+            )))
+        }
+    };
+
+    // Proxies may send the absolute form `http://host/path`.
+    let target = match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|idx| &rest[idx..]).unwrap_or("/"),
+        None => target,
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let expects_continue = map_get(&headers, "expect")
+        .map(|v| v.to_string().eq_ignore_ascii_case("100-continue"))
+        .unwrap_or(false);
+    if expects_continue {
+        socket
+            .send(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .map_err(|e| socket_error("Failed to write HTTP response".to_string(), e))?;
+    }
+    let body = read_body(socket, &headers, false, Some(max_body))?;
+    let peer = socket.peer_addr().unwrap_or_default();
+
+    Ok(Value::Map(vec![
+        ("method".to_string(), Value::String(method.to_uppercase())),
+        (
+            "path".to_string(),
+            Value::String(percent_decode(path, false)),
+        ),
+        ("query".to_string(), Value::Map(parse_query(query))),
+        ("headers".to_string(), Value::Map(headers)),
+        (
+            "body".to_string(),
+            Value::String(String::from_utf8_lossy(&body).to_string()),
+        ),
+        ("peer".to_string(), Value::String(peer)),
+    ]))
+}
+
+fn reason_phrase(status: i64) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// `None` when the connection itself failed.
+pub fn reject_status(error: &Error) -> Option<i64> {
+    match error.kind.as_str() {
+        "http" => Some(400),
+        TOO_LARGE => Some(413),
+        HEAD_TOO_LARGE => Some(431),
+        _ => None,
+    }
+}
+
+pub fn text_response(status: i64, body: &str) -> Value {
+    Value::Map(vec![
+        ("status".to_string(), Value::Int(status)),
+        ("body".to_string(), Value::String(body.to_string())),
+    ])
+}
+
+/// The connection is closed afterwards, so every response says so.
+pub fn write_response(socket: &mut Socket, method: &str, response: &Value) -> Result<(), Error> {
+    let (status, extra, body) = match response {
+        Value::Map(map) => (
+            map_get(map, "status").map(|v| v.to_int()).unwrap_or(200),
+            match map_get(map, "headers") {
+                Some(Value::Map(headers)) => headers.clone(),
+                _ => Map::new(),
+            },
+            map_get(map, "body").map(body_bytes).unwrap_or_default(),
+        ),
+        Value::Nil => (204, Map::new(), Vec::new()),
+        other => (200, Map::new(), body_bytes(other)),
+    };
+
+    let mut headers: Map = Vec::new();
+    let has_body = status != 204 && status != 304 && !(100..200).contains(&status);
+    if has_body {
+        headers.push((
+            "Content-Type".to_string(),
+            Value::String("text/plain; charset=utf-8".to_string()),
+        ));
+        headers.push((
+            "Content-Length".to_string(),
+            Value::String(body.len().to_string()),
+        ));
+    }
+    headers.push(("Connection".to_string(), Value::String("close".to_string())));
+    for (name, value) in extra {
+        // The framing headers always describe the body actually sent.
+        if name.eq_ignore_ascii_case("content-length")
+            || name.eq_ignore_ascii_case("transfer-encoding")
+            || name.eq_ignore_ascii_case("connection")
+        {
+            continue;
+        }
+        headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+        headers.push((name, value));
+    }
+
+    let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason_phrase(status));
+    for (name, value) in &headers {
+        head.push_str(&header_line(name, value)?);
+    }
+    head.push_str("\r\n");
+
+    let mut payload = head.into_bytes();
+    if has_body && method != "HEAD" {
+        payload.extend_from_slice(&body);
+    }
+    socket
+        .send(&payload)
+        .map_err(|e| socket_error("Failed to write HTTP response".to_string(), e))
+}
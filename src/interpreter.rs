@@ -4,19 +4,34 @@
 // License: MPL 2.0
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::arrays;
+use crate::bytes;
+use crate::csv;
+use crate::error::Error;
+use crate::files::{self, FileHandle};
+use crate::http;
+use crate::json;
 use crate::parser::{BinOp, Expr, Statement, UnaryOp};
+use crate::process::{self, Process};
+use crate::regexp;
 use crate::runtime::Runtime;
-use crate::value::Value;
+use crate::socket::{self, socket_error, Datagram, Listener};
+use crate::template;
+use crate::tls;
+use crate::value::{map_get, map_insert, Function, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::io::{self, Read, Write};
-use std::net::TcpStream;
+use std::io::{self, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
 
 pub struct Interpreter {
     runtime: Runtime,
     current_return: Option<Value>,
+    current_line: usize,
     base_dirs: Vec<PathBuf>,
 }
 
@@ -25,6 +40,7 @@ impl Interpreter {
         Interpreter {
             runtime: Runtime::new(),
             current_return: None,
+            current_line: 0,
             base_dirs: vec![env::current_dir().unwrap_or_else(|_| PathBuf::from("."))],
         }
     }
@@ -47,6 +63,7 @@ impl Interpreter {
         self.base_dirs.last()
     }
 
+    /// Relative paths are tried against the script's directory first.
     fn resolve_include_path(&self, path: &str) -> PathBuf {
         let specified = Path::new(path);
         if specified.is_absolute() {
@@ -73,28 +90,59 @@ impl Interpreter {
     }
 
     pub fn execute(&mut self, statements: Vec<Statement>) -> Result<(), String> {
+        self.execute_block(&statements)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn execute_block(&mut self, statements: &[Statement]) -> Result<Option<Value>, Error> {
         for stmt in statements {
-            self.execute_statement(&stmt)?;
+            if let Some(value) = self.execute_statement(stmt)? {
+                return Ok(Some(value));
+            }
         }
-        Ok(())
+        Ok(None)
+    }
+
+    fn execute_statement(&mut self, stmt: &Statement) -> Result<Option<Value>, Error> {
+        self.run_statement(stmt)
+            .map_err(|e| e.at_line(self.current_line))
     }
 
-    fn execute_statement(&mut self, stmt: &Statement) -> Result<Option<Value>, String> {
+    fn run_statement(&mut self, stmt: &Statement) -> Result<Option<Value>, Error> {
         match stmt {
+            Statement::Line { line } => {
+                self.current_line = *line;
+                Ok(None)
+            }
             Statement::Assignment { var, value } => {
                 let val = self.eval_expr(value)?;
                 self.runtime.set_var(var.clone(), val);
                 Ok(None)
             }
             Statement::ArrayAssignment { var, index, value } => {
-                let idx = self.eval_expr(index)?.to_int() as usize;
+                let key = self.eval_expr(index)?;
                 let val = self.eval_expr(value)?;
 
                 let mut array = self.runtime.get_var(var);
-                if let Value::Array(ref mut arr) = array {
-                    if idx < arr.len() {
-                        arr[idx] = val;
+                match array {
+                    Value::Array(ref mut arr) => {
+                        let idx = key.to_int() as usize;
+                        if idx < arr.len() {
+                            arr[idx] = val;
+                        }
+                    }
+                    Value::Map(ref mut map) => map_insert(map, key.to_string(), val),
+                    Value::Bytes(ref mut bytes) => {
+                        let idx = key.to_int() as usize;
+                        if idx < bytes.len() {
+                            bytes[idx] = bytes::to_byte(&val).map_err(|e| Error::new("type", e))?;
+                        }
                     }
+                    Value::Nil if matches!(key, Value::String(_)) => {
+                        array = Value::Map(vec![(key.to_string(), val)]);
+                    }
+                    _ => {}
                 }
                 self.runtime.set_var(var.clone(), array);
                 Ok(None)
@@ -107,64 +155,44 @@ impl Interpreter {
             } => {
                 let cond = self.eval_expr(condition)?;
                 if cond.is_truthy() {
-                    for s in then_body {
-                        if let Ok(Some(v)) = self.execute_statement(s) {
-                            return Ok(Some(v));
-                        }
-                    }
-                } else {
-                    let mut executed = false;
-                    for (elif_cond, elif_body) in elseif_parts {
-                        let elif_cond_val = self.eval_expr(elif_cond)?;
-                        if elif_cond_val.is_truthy() {
-                            for s in elif_body {
-                                if let Ok(Some(v)) = self.execute_statement(s) {
-                                    return Ok(Some(v));
-                                }
-                            }
-                            executed = true;
-                            break;
-                        }
-                    }
+                    return self.execute_block(then_body);
+                }
 
-                    if !executed {
-                        if let Some(else_stmts) = else_body {
-                            for s in else_stmts {
-                                if let Ok(Some(v)) = self.execute_statement(s) {
-                                    return Ok(Some(v));
-                                }
-                            }
-                        }
+                for (elif_cond, elif_body) in elseif_parts {
+                    let elif_cond_val = self.eval_expr(elif_cond)?;
+                    if elif_cond_val.is_truthy() {
+                        return self.execute_block(elif_body);
                     }
                 }
+
+                if let Some(else_stmts) = else_body {
+                    return self.execute_block(else_stmts);
+                }
                 Ok(None)
             }
             Statement::While { condition, body } => {
                 while self.eval_expr(condition)?.is_truthy() {
-                    for s in body {
-                        if let Ok(Some(v)) = self.execute_statement(s) {
-                            return Ok(Some(v));
-                        }
+                    if let Some(v) = self.execute_block(body)? {
+                        return Ok(Some(v));
                     }
                 }
                 Ok(None)
             }
             Statement::Printf { format, args } => {
-                let mut output = String::new();
-
-                if !format.is_empty() {
-                    output.push_str(&format);
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval_expr(arg)?);
                 }
 
-                for arg in args {
-                    let val = self.eval_expr(arg)?;
-                    match val {
-                        Value::Int(n) => output.push_str(&n.to_string()),
-                        Value::String(s) => output.push_str(&s),
-                        Value::Array(arr) => output.push_str(&format!("[Array({})]", arr.len())),
-                        Value::Nil => (),
+                let mut output = if template::is_template(format, values.len()) {
+                    template::render(format, &values)?
+                } else {
+                    let mut output = format.clone();
+                    for value in &values {
+                        output.push_str(&template::display(value));
                     }
-                }
+                    output
+                };
 
                 output = output.replace("\\n", "\n").replace("\\t", "\t");
 
@@ -182,15 +210,14 @@ impl Interpreter {
                     .read_line(&mut input)
                     .map_err(|e| format!("Failed to read input: {}", e))?;
 
-                let trimmed = input
-                    .trim_end_matches(|c| c == '\n' || c == '\r')
-                    .to_string();
+                let trimmed = input.trim_end_matches(['\n', '\r']).to_string();
                 self.runtime.set_var(var.clone(), Value::String(trimmed));
                 Ok(None)
             }
             Statement::Inc { var, value } => {
                 let current = self.runtime.get_var(var);
                 let inc_val = self.eval_expr(value)?;
+                check_arithmetic(&BinOp::Add, &current, &inc_val)?;
                 let result = current.add(&inc_val);
                 self.runtime.set_var(var.clone(), result);
                 Ok(None)
@@ -198,6 +225,7 @@ impl Interpreter {
             Statement::Dec { var, value } => {
                 let current = self.runtime.get_var(var);
                 let dec_val = self.eval_expr(value)?;
+                check_arithmetic(&BinOp::Subtract, &current, &dec_val)?;
                 let result = current.subtract(&dec_val);
                 self.runtime.set_var(var.clone(), result);
                 Ok(None)
@@ -252,18 +280,54 @@ impl Interpreter {
                 self.runtime.set_var(array.clone(), arr);
                 Ok(None)
             }
+            Statement::Sort { array } => {
+                let mut arr = self.runtime.get_var(array);
+                if let Value::Array(ref mut elements) = arr {
+                    *elements = arrays::sort(elements);
+                }
+                self.runtime.set_var(array.clone(), arr);
+                Ok(None)
+            }
+            Statement::Reverse { array } => {
+                let mut arr = self.runtime.get_var(array);
+                if let Value::Array(ref mut elements) = arr {
+                    elements.reverse();
+                }
+                self.runtime.set_var(array.clone(), arr);
+                Ok(None)
+            }
+            Statement::Unique { array } => {
+                let mut arr = self.runtime.get_var(array);
+                if let Value::Array(ref mut elements) = arr {
+                    *elements = arrays::unique(elements);
+                }
+                self.runtime.set_var(array.clone(), arr);
+                Ok(None)
+            }
+            Statement::Concat { array, value } => {
+                let arr = self.runtime.get_var(array);
+                let val = self.eval_expr(value)?;
+
+                let combined = match arr {
+                    Value::Array(_) => arrays::concat(&[arr, val]),
+                    _ => arrays::concat(&[val]),
+                };
+
+                self.runtime.set_var(array.clone(), Value::Array(combined));
+                Ok(None)
+            }
             Statement::Sockopen { name, host, port } => {
-                let host_val = self.eval_expr(host)?.to_string();
-                let port_val = self.eval_expr(port)?.to_int() as u16;
-                let addr = format!("{}:{}", host_val, port_val);
+                let host_val = self.eval_expr(host)?;
+                let port_val = match port {
+                    Some(port) => Some(self.eval_expr(port)?),
+                    None => None,
+                };
+                let addr = socket_address(&host_val, port_val.as_ref());
 
-                match TcpStream::connect(&addr) {
-                    Ok(stream) => {
-                        self.runtime.set_socket(name.clone(), stream);
-                        Ok(None)
-                    }
-                    Err(_) => Err(format!("Failed to connect to {}", addr)),
-                }
+                socket::connect(&addr, self.runtime.timeout(name))
+                    .and_then(|socket| self.runtime.set_socket(name.clone(), socket))
+                    .map_err(|e| socket_error(format!("Failed to connect to {}", addr), e))?;
+                Ok(None)
             }
             Statement::Sockclose { name } => {
                 self.runtime.remove_socket(name);
@@ -271,30 +335,32 @@ impl Interpreter {
             }
             Statement::Sockwrite { name, data } => {
                 let data_val = self.eval_expr(data)?;
-                let data_str = data_val.to_string();
 
-                if let Some(stream) = self.runtime.get_socket(name) {
-                    stream.write_all(data_str.as_bytes()).ok();
-                    stream.flush().ok();
-                }
+                let socket = self
+                    .runtime
+                    .get_socket(name)
+                    .ok_or_else(|| unknown_socket(name))?;
+                socket
+                    .send(&data_val.to_bytes())
+                    .map_err(|e| socket_error(format!("Failed to write to '{}'", name), e))?;
 
                 Ok(None)
             }
-            Statement::Sockread { name, var } => {
-                if let Some(stream) = self.runtime.get_socket(name) {
-                    let mut buffer = [0; 1024];
-                    match stream.read(&mut buffer) {
-                        Ok(n) => {
-                            let data = String::from_utf8_lossy(&buffer[..n]).to_string();
-                            self.runtime.set_var(var.clone(), Value::String(data));
-                        }
-                        Err(_) => {
-                            self.runtime
-                                .set_var(var.clone(), Value::String(String::new()));
-                        }
-                    }
-                }
+            Statement::Sockread { name, var, max } => {
+                let max = match max {
+                    Some(expr) => self.eval_expr(expr)?.to_int().max(1) as usize,
+                    None => 1024,
+                };
+                let socket = self
+                    .runtime
+                    .get_socket(name)
+                    .ok_or_else(|| unknown_socket(name))?;
+                let bytes = socket
+                    .receive(max)
+                    .map_err(|e| socket_error(format!("Failed to read from '{}'", name), e))?;
 
+                let data = String::from_utf8_lossy(&bytes).to_string();
+                self.runtime.set_var(var.clone(), Value::String(data));
                 Ok(None)
             }
             Statement::Include { path } => {
@@ -302,14 +368,22 @@ impl Interpreter {
                 match fs::read_to_string(&resolved_path) {
                     Ok(content) => {
                         let mut parser = crate::parser::Parser::new(&content);
-                        let stmts = parser.parse();
+                        let stmts = parser.parse().map_err(|e| {
+                            let message = format!(
+                                "{} at line {} of '{}'",
+                                e.message,
+                                e.line,
+                                resolved_path.display()
+                            );
+                            Error::new("syntax", message)
+                        })?;
 
                         let parent_dir = resolved_path.parent().map(|p| p.to_path_buf());
                         if let Some(dir) = parent_dir.clone() {
                             self.push_base_dir(dir);
                         }
 
-                        let exec_result = self.execute(stmts);
+                        let exec_result = self.execute_block(&stmts);
 
                         if parent_dir.is_some() {
                             self.pop_base_dir();
@@ -318,35 +392,60 @@ impl Interpreter {
                         exec_result?;
                         Ok(None)
                     }
-                    Err(e) => Err(format!("Failed to include file: {}", e)),
+                    Err(e) => Err(Error::new(
+                        "include",
+                        format!("Failed to include file: {}", e),
+                    )),
                 }
             }
-            Statement::FunctionDef { name, body } => {
-                self.runtime.define_function(name.clone(), body.clone());
+            Statement::FunctionDef { name, params, body } => {
+                let function = Function {
+                    name: Some(name.clone()),
+                    params: params.clone(),
+                    body: body.clone(),
+                    captured: HashMap::new(),
+                };
+                self.runtime
+                    .define_function(name.clone(), Rc::new(function));
                 Ok(None)
             }
             Statement::FunctionCall { name, args } => {
-                // Built-ins bypass user-defined lookup, so handle them early.
-                if name == "sleep" {
-                    if let Some(arg) = args.first() {
-                        let val = self.eval_expr(arg)?;
-                        let seconds = val.to_int() as u64;
-                        std::thread::sleep(std::time::Duration::from_secs(seconds));
-                    }
+                if args.is_empty() && self.resolve_function(name).is_none() && name != "sleep" {
+                    eprintln!("Warning: function '{}' not defined", name);
                     return Ok(None);
                 }
 
-                if let Some(body) = self.runtime.get_function(name) {
-                    for stmt in &body {
-                        if let Ok(Some(val)) = self.execute_statement(stmt) {
-                            return Ok(Some(val));
-                        }
+                // The call's return value is discarded so it does not end the
+                // enclosing function early.
+                self.call_builtin(name, args)?;
+                Ok(None)
+            }
+            Statement::Try {
+                body,
+                catch_var,
+                catch_body,
+                finally_body,
+            } => {
+                let mut outcome = self.execute_block(body);
+
+                if let (Err(error), Some(handler)) = (&outcome, catch_body) {
+                    if let Some(var) = catch_var {
+                        self.runtime.set_var(var.clone(), error.to_value());
                     }
-                    Ok(None)
-                } else {
-                    eprintln!("Warning: function '{}' not defined", name);
-                    Ok(None)
+                    outcome = self.execute_block(handler);
                 }
+
+                if let Some(finally_stmts) = finally_body {
+                    if let Some(v) = self.execute_block(finally_stmts)? {
+                        return Ok(Some(v));
+                    }
+                }
+
+                outcome
+            }
+            Statement::Throw { value } => {
+                let val = self.eval_expr(value)?;
+                Err(Error::thrown(val))
             }
             Statement::Return { value } => {
                 if let Some(expr) = value {
@@ -361,66 +460,78 @@ impl Interpreter {
         }
     }
 
-    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, String> {
+    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
         match expr {
             Expr::Int(n) => Ok(Value::Int(*n)),
+            Expr::Bool(b) => Ok(Value::Bool(*b)),
+            Expr::Nil => Ok(Value::Nil),
             Expr::String(s) => Ok(Value::String(s.clone())),
-            Expr::Variable(name) => Ok(self.runtime.get_var(name)),
+            Expr::Bytes(bytes) => Ok(Value::Bytes(bytes.clone())),
+            Expr::Variable(name) => Ok(self
+                .runtime
+                .lookup_var(name)
+                .or_else(|| self.runtime.get_function(name).map(Value::Function))
+                .unwrap_or(Value::Nil)),
             Expr::Binary { left, op, right } => {
                 let left_val = self.eval_expr(left)?;
+
+                // Logical operators short-circuit and yield the operand that
+                // decided the result, so `$x = $input OR "default"` works.
+                match op {
+                    BinOp::And if !left_val.is_truthy() => return Ok(left_val),
+                    BinOp::Or if left_val.is_truthy() => return Ok(left_val),
+                    BinOp::Coalesce if !matches!(left_val, Value::Nil) => return Ok(left_val),
+                    BinOp::And | BinOp::Or | BinOp::Coalesce => return self.eval_expr(right),
+                    _ => {}
+                }
+
                 let right_val = self.eval_expr(right)?;
 
+                if matches!(
+                    op,
+                    BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Modulo
+                ) {
+                    check_arithmetic(op, &left_val, &right_val)?;
+                }
+
                 Ok(match op {
                     BinOp::Add => left_val.add(&right_val),
                     BinOp::Subtract => left_val.subtract(&right_val),
                     BinOp::Multiply => left_val.multiply(&right_val),
                     BinOp::Divide => left_val.divide(&right_val),
                     BinOp::Modulo => left_val.modulo(&right_val),
-                    BinOp::Equal => Value::Int(if left_val.equals(&right_val) { 1 } else { 0 }),
-                    BinOp::NotEqual => Value::Int(if !left_val.equals(&right_val) { 1 } else { 0 }),
-                    BinOp::Less => {
-                        if let Some(std::cmp::Ordering::Less) = left_val.compare(&right_val) {
-                            Value::Int(1)
-                        } else {
-                            Value::Int(0)
-                        }
-                    }
-                    BinOp::LessEqual => match left_val.compare(&right_val) {
-                        Some(std::cmp::Ordering::Less) | Some(std::cmp::Ordering::Equal) => {
-                            Value::Int(1)
-                        }
-                        _ => Value::Int(0),
-                    },
-                    BinOp::Greater => {
-                        if let Some(std::cmp::Ordering::Greater) = left_val.compare(&right_val) {
-                            Value::Int(1)
-                        } else {
-                            Value::Int(0)
-                        }
-                    }
-                    BinOp::GreaterEqual => match left_val.compare(&right_val) {
-                        Some(std::cmp::Ordering::Greater) | Some(std::cmp::Ordering::Equal) => {
-                            Value::Int(1)
-                        }
-                        _ => Value::Int(0),
-                    },
-                    BinOp::And => Value::Int(if left_val.is_truthy() && right_val.is_truthy() {
-                        1
-                    } else {
-                        0
-                    }),
-                    BinOp::Or => Value::Int(if left_val.is_truthy() || right_val.is_truthy() {
-                        1
-                    } else {
-                        0
-                    }),
+                    BinOp::Equal => Value::Bool(left_val.equals(&right_val)),
+                    BinOp::NotEqual => Value::Bool(!left_val.equals(&right_val)),
+                    BinOp::Less => Value::Bool(matches!(
+                        left_val.compare(&right_val),
+                        Some(std::cmp::Ordering::Less)
+                    )),
+                    BinOp::LessEqual => Value::Bool(matches!(
+                        left_val.compare(&right_val),
+                        Some(std::cmp::Ordering::Less) | Some(std::cmp::Ordering::Equal)
+                    )),
+                    BinOp::Greater => Value::Bool(matches!(
+                        left_val.compare(&right_val),
+                        Some(std::cmp::Ordering::Greater)
+                    )),
+                    BinOp::GreaterEqual => Value::Bool(matches!(
+                        left_val.compare(&right_val),
+                        Some(std::cmp::Ordering::Greater) | Some(std::cmp::Ordering::Equal)
+                    )),
+                    BinOp::Matches => Value::Bool(regexp::is_match(&left_val, &right_val)?),
+                    BinOp::And | BinOp::Or | BinOp::Coalesce => unreachable!(),
                 })
             }
             Expr::Unary { op, expr } => {
                 let val = self.eval_expr(expr)?;
                 Ok(match op {
-                    UnaryOp::Not => Value::Int(if val.is_truthy() { 0 } else { 1 }),
-                    UnaryOp::Negate => Value::Int(-val.to_int()),
+                    UnaryOp::Not => Value::Bool(!val.is_truthy()),
+                    UnaryOp::Negate => {
+                        if let Value::Bool(_) = val {
+                            return Err(Error::new("type", "Cannot negate a boolean"));
+                        }
+                        Value::Int(-val.to_int())
+                    }
                 })
             }
             Expr::Array(elements) => {
@@ -430,12 +541,29 @@ impl Interpreter {
                 }
                 Ok(Value::Array(values))
             }
+            Expr::Map(entries) => {
+                let mut map = Vec::with_capacity(entries.len());
+                for (key, value) in entries {
+                    let key = self.eval_expr(key)?.to_string();
+                    let value = self.eval_expr(value)?;
+                    map_insert(&mut map, key, value);
+                }
+                Ok(Value::Map(map))
+            }
             Expr::Index { expr, index } => {
                 let arr = self.eval_expr(expr)?;
-                let idx = self.eval_expr(index)?.to_int() as usize;
+                let key = self.eval_expr(index)?;
+                let idx = key.to_int() as usize;
 
                 match arr {
+                    Value::Map(map) => Ok(map_get(&map, &key.to_string())
+                        .cloned()
+                        .unwrap_or(Value::Nil)),
                     Value::Array(elements) => Ok(elements.get(idx).cloned().unwrap_or(Value::Nil)),
+                    Value::Bytes(bytes) => Ok(bytes
+                        .get(idx)
+                        .map(|&byte| Value::Int(byte as i64))
+                        .unwrap_or(Value::Nil)),
                     Value::String(s) => {
                         let chars: Vec<char> = s.chars().collect();
                         if idx < chars.len() {
@@ -447,100 +575,901 @@ impl Interpreter {
                     _ => Ok(Value::Nil),
                 }
             }
-            Expr::FunctionCall { name, args } => {
-                match name.as_str() {
-                    "len" | "strlen" => {
-                        if let Some(arg) = args.first() {
-                            let val = self.eval_expr(arg)?;
-                            match val {
-                                Value::String(s) => Ok(Value::Int(s.len() as i64)),
-                                Value::Array(arr) => Ok(Value::Int(arr.len() as i64)),
-                                _ => Ok(Value::Int(0)),
-                            }
-                        } else {
-                            Ok(Value::Int(0))
-                        }
+            Expr::FunctionCall { name, args } => self.call_builtin(name, args),
+            Expr::Lambda { params, body } => Ok(Value::Function(Rc::new(Function {
+                name: None,
+                params: Some(params.clone()),
+                body: body.clone(),
+                captured: self.runtime.current_locals(),
+            }))),
+        }
+    }
+
+    fn call_builtin(&mut self, name: &str, args: &[Expr]) -> Result<Value, Error> {
+        match name {
+            "len" | "strlen" => {
+                if let Some(arg) = args.first() {
+                    let val = self.eval_expr(arg)?;
+                    match val {
+                        Value::String(s) | Value::Number(s) => Ok(Value::Int(s.len() as i64)),
+                        Value::Bytes(bytes) => Ok(Value::Int(bytes.len() as i64)),
+                        Value::Array(arr) => Ok(Value::Int(arr.len() as i64)),
+                        Value::Map(map) => Ok(Value::Int(map.len() as i64)),
+                        _ => Ok(Value::Int(0)),
                     }
-                    "shell" => {
-                        if let Some(arg) = args.first() {
-                            let val = self.eval_expr(arg)?;
-                            let cmd_str = val.to_string();
-
-                            let output = if cfg!(target_os = "windows") {
-                                Command::new("cmd").args(["/C", &cmd_str]).output()
-                            } else {
-                                Command::new("sh").arg("-c").arg(&cmd_str).output()
-                            };
-
-                            match output {
-                                Ok(result) => {
-                                    let mut stdout =
-                                        String::from_utf8_lossy(&result.stdout).to_string();
-                                    // Trim trailing newline so pipelines behave predictably.
-                                    if stdout.ends_with('\n') {
-                                        stdout.pop();
-                                        if stdout.ends_with('\r') {
-                                            stdout.pop();
-                                        }
-                                    }
-                                    Ok(Value::String(stdout))
+                } else {
+                    Ok(Value::Int(0))
+                }
+            }
+            "shell" => {
+                if let Some(arg) = args.first() {
+                    let val = self.eval_expr(arg)?;
+                    let cmd_str = val.to_string();
+
+                    match process::shell_command(&cmd_str).output() {
+                        Ok(result) => {
+                            let mut stdout = String::from_utf8_lossy(&result.stdout).to_string();
+                            // Trim trailing newline so pipelines behave predictably.
+                            if stdout.ends_with('\n') {
+                                stdout.pop();
+                                if stdout.ends_with('\r') {
+                                    stdout.pop();
                                 }
-                                Err(_) => Ok(Value::String(String::new())),
                             }
-                        } else {
-                            Ok(Value::String(String::new()))
+                            Ok(Value::String(stdout))
                         }
+                        Err(_) => Ok(Value::String(String::new())),
                     }
-                    "number" => {
-                        if let Some(arg) = args.first() {
-                            let val = self.eval_expr(arg)?;
-                            match val {
-                                Value::Int(n) => Ok(Value::Int(n)),
-                                Value::String(s) => {
-                                    let trimmed = s.trim();
-                                    match trimmed.parse::<i64>() {
-                                        Ok(n) => Ok(Value::Int(n)),
-                                        Err(_) => Ok(Value::Int(0)),
-                                    }
-                                }
-                                Value::Array(_) | Value::Nil => Ok(Value::Int(0)),
+                } else {
+                    Ok(Value::String(String::new()))
+                }
+            }
+            "number" => {
+                if let Some(arg) = args.first() {
+                    let val = self.eval_expr(arg)?;
+                    match val {
+                        Value::Int(n) => Ok(Value::Int(n)),
+                        Value::Bool(b) => Ok(Value::Int(b as i64)),
+                        Value::String(s) => {
+                            let trimmed = s.trim();
+                            match trimmed.parse::<i64>() {
+                                Ok(n) => Ok(Value::Int(n)),
+                                Err(_) => Ok(Value::Int(0)),
                             }
-                        } else {
-                            Ok(Value::Int(0))
                         }
+                        Value::Number(s) => Ok(Value::Int(s.parse::<f64>().unwrap_or(0.0) as i64)),
+                        Value::Bytes(_)
+                        | Value::Array(_)
+                        | Value::Map(_)
+                        | Value::Function(_)
+                        | Value::Nil => Ok(Value::Int(0)),
                     }
-                    "lower" => {
-                        if let Some(arg) = args.first() {
-                            let val = self.eval_expr(arg)?;
-                            Ok(Value::String(val.to_string().to_lowercase()))
-                        } else {
-                            Ok(Value::String(String::new()))
-                        }
+                } else {
+                    Ok(Value::Int(0))
+                }
+            }
+            "lower" => {
+                if let Some(arg) = args.first() {
+                    let val = self.eval_expr(arg)?;
+                    Ok(Value::String(val.to_string().to_lowercase()))
+                } else {
+                    Ok(Value::String(String::new()))
+                }
+            }
+            "upper" => {
+                if let Some(arg) = args.first() {
+                    let val = self.eval_expr(arg)?;
+                    Ok(Value::String(val.to_string().to_uppercase()))
+                } else {
+                    Ok(Value::String(String::new()))
+                }
+            }
+            "sleep" => {
+                if let Some(arg) = args.first() {
+                    let val = self.eval_expr(arg)?;
+                    let seconds = val.to_int() as u64;
+                    std::thread::sleep(std::time::Duration::from_secs(seconds));
+                    Ok(Value::Nil)
+                } else {
+                    Ok(Value::Nil)
+                }
+            }
+            _ => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval_expr(arg)?);
+                }
+
+                if let Some(function) = self.resolve_function(name) {
+                    return self.call_function(&function, values);
+                }
+
+                if let Some(result) = self.call_higher_order(name, &values) {
+                    return result;
+                }
+
+                if let Some(result) = self.call_socket(name, args, &values) {
+                    return result;
+                }
+
+                if let Some(result) = self.call_file_handle(name, args, &values) {
+                    return result;
+                }
+
+                if let Some(result) = self.call_process(name, args, &values) {
+                    return result;
+                }
+
+                if let Some(result) = http::call(name, &values) {
+                    return result;
+                }
+
+                if let Some(result) = process::call(name, &values) {
+                    return result;
+                }
+
+                if let Some(result) = arrays::call(name, &values) {
+                    return result;
+                }
+
+                if let Some(result) = bytes::call(name, &values) {
+                    return result;
+                }
+
+                if let Some(result) = template::call(name, &values) {
+                    return result;
+                }
+
+                if let Some(result) = regexp::call(name, &values) {
+                    return result;
+                }
+
+                if let Some(result) = json::call(name, &values) {
+                    return result;
+                }
+
+                if let Some(result) =
+                    csv::call(name, &values, |path| self.resolve_include_path(path))
+                {
+                    return result;
+                }
+
+                if let Some(result) =
+                    files::call(name, &values, |path| self.resolve_include_path(path))
+                {
+                    return result;
+                }
+
+                eprintln!("Warning: unknown function '{}'", name);
+                Ok(Value::Nil)
+            }
+        }
+    }
+
+    /// A variable holding a function wins over a `func` of the same name.
+    fn resolve_function(&self, name: &str) -> Option<Rc<Function>> {
+        if let Some(Value::Function(function)) = self.runtime.lookup_var(name) {
+            return Some(function);
+        }
+        self.runtime.get_function(name)
+    }
+
+    fn expect_callable(&self, caller: &str, value: Option<&Value>) -> Result<Rc<Function>, String> {
+        match value {
+            Some(Value::Function(function)) => Ok(function.clone()),
+            Some(Value::String(fname)) => self
+                .resolve_function(fname)
+                .ok_or_else(|| format!("{}(): function '{}' not defined", caller, fname)),
+            _ => Err(format!("{}() expects a function argument", caller)),
+        }
+    }
+
+    /// Missing arguments are nil; extra ones are ignored.
+    fn call_function(&mut self, function: &Rc<Function>, args: Vec<Value>) -> Result<Value, Error> {
+        let caller_line = self.current_line;
+        let result = match &function.params {
+            Some(params) => {
+                let mut locals = function.captured.clone();
+                let mut args = args.into_iter();
+                for param in params {
+                    let value = args.next().unwrap_or(Value::Nil);
+                    locals.insert(param.clone(), Rc::new(RefCell::new(value)));
+                }
+
+                self.runtime.push_scope(locals);
+                let result = self.execute_block(&function.body);
+                self.runtime.pop_scope();
+                result
+            }
+            None => self.execute_block(&function.body),
+        };
+        self.current_line = caller_line;
+
+        result.map(|value| value.unwrap_or(Value::Nil))
+    }
+
+    fn call_socket(
+        &mut self,
+        name: &str,
+        exprs: &[Expr],
+        args: &[Value],
+    ) -> Option<Result<Value, Error>> {
+        let arg = |idx: usize| args.get(idx).cloned().unwrap_or(Value::Nil);
+
+        let result = match name {
+            "sockopen_tls" => {
+                let host = arg(1).to_string();
+                let addr = format!("{}:{}", host, arg(2).to_int());
+                let timeout = self.runtime.timeout(&arg(0).to_string());
+                tls::TlsOptions::from_value(&host, &arg(3))
+                    .and_then(|options| tls::connect(&addr, &options, timeout))
+                    .and_then(|socket| {
+                        self.runtime
+                            .set_socket(arg(0).to_string(), socket)
+                            .map_err(|e| socket_error(format!("Failed to connect to {}", addr), e))
+                    })
+                    .map(|_| Value::Bool(true))
+            }
+            "socklisten" => {
+                let addr = socket_address(&arg(1), args.get(2));
+                Listener::bind(&addr)
+                    .map(|listener| {
+                        self.runtime.set_listener(arg(0).to_string(), listener);
+                        Value::Bool(true)
+                    })
+                    .map_err(|e| {
+                        Error::new("socket", format!("Failed to listen on {}: {}", addr, e))
+                    })
+            }
+            "sockaccept" => {
+                let server = arg(0).to_string();
+                let accepted = match self.runtime.get_listener(&server) {
+                    Some(listener) => listener.accept(),
+                    None => return Some(Err(unknown_socket(&server))),
+                };
+                // A listener with a timeout gives up with false when nobody
+                // connects in time.
+                accepted
+                    .and_then(|socket| match socket {
+                        Some(socket) => self
+                            .runtime
+                            .set_socket(arg(1).to_string(), socket)
+                            .map(|_| true),
+                        None => Ok(false),
+                    })
+                    .map(Value::Bool)
+                    .map_err(|e| socket_error(format!("Failed to accept on '{}'", server), e))
+            }
+            "sockpeer" => {
+                let sock = arg(0).to_string();
+                match self.runtime.get_socket(&sock) {
+                    Some(stream) => stream
+                        .peer_addr()
+                        .map(Value::String)
+                        .map_err(|e| Error::new("socket", format!("{}: {}", sock, e))),
+                    None => Err(unknown_socket(&sock)),
+                }
+            }
+            "sockport" => {
+                let sock = arg(0).to_string();
+                let local = match self.runtime.get_listener(&sock) {
+                    Some(listener) => Some(listener.local_port()),
+                    None => match self.runtime.get_datagram(&sock) {
+                        Some(datagram) => Some(datagram.local_addr().map(|addr| addr.port())),
+                        None => self.runtime.get_socket(&sock).map(|s| s.local_port()),
+                    },
+                };
+                match local {
+                    Some(Ok(port)) => Ok(Value::Int(port as i64)),
+                    Some(Err(e)) => Err(Error::new("socket", format!("{}: {}", sock, e))),
+                    None => Err(unknown_socket(&sock)),
+                }
+            }
+            "sockreadline" | "sockreadn" | "sockreadall" | "sockreadnbytes" => {
+                let sock = arg(0).to_string();
+                let (target, wanted) = match name {
+                    "sockreadn" | "sockreadnbytes" => {
+                        (exprs.get(2), Some(arg(1).to_int().max(0) as usize))
                     }
-                    "upper" => {
-                        if let Some(arg) = args.first() {
-                            let val = self.eval_expr(arg)?;
-                            Ok(Value::String(val.to_string().to_uppercase()))
-                        } else {
-                            Ok(Value::String(String::new()))
+                    _ => (exprs.get(1), None),
+                };
+                let socket = match self.runtime.get_socket(&sock) {
+                    Some(socket) => socket,
+                    None => return Some(Err(unknown_socket(&sock))),
+                };
+
+                // Each form returns false once the connection has nothing
+                // more to give: no line left, fewer than n bytes, or no data.
+                let read = match wanted {
+                    Some(n) => match socket.read_exact(n) {
+                        Ok(bytes) => Ok((true, bytes)),
+                        // Hand over whatever arrived before the peer closed.
+                        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                            socket.read_to_end().map(|bytes| (false, bytes))
                         }
+                        Err(e) => Err(e),
+                    },
+                    None if name == "sockreadline" => socket
+                        .read_line()
+                        .map(|line| (line.is_some(), line.unwrap_or_default())),
+                    None => socket.read_to_end().map(|bytes| (!bytes.is_empty(), bytes)),
+                };
+
+                match read {
+                    Ok((complete, bytes)) => {
+                        let data = match name {
+                            "sockreadnbytes" => Value::Bytes(bytes),
+                            _ => Value::String(String::from_utf8_lossy(&bytes).to_string()),
+                        };
+                        self.assign_out(name, target, data)
+                            .map(|_| Value::Bool(complete))
                     }
-                    "sleep" => {
-                        if let Some(arg) = args.first() {
-                            let val = self.eval_expr(arg)?;
-                            let seconds = val.to_int() as u64;
-                            std::thread::sleep(std::time::Duration::from_secs(seconds));
-                            Ok(Value::Nil)
-                        } else {
-                            Ok(Value::Nil)
-                        }
+                    Err(e) => Err(socket_error(format!("Failed to read from '{}'", sock), e)),
+                }
+            }
+            "sockreadbytes" => {
+                // Binary counterpart of `sockread`: whatever arrives in one
+                // read, untouched; false once the peer has closed.
+                let sock = arg(0).to_string();
+                let max = args
+                    .get(2)
+                    .map(|v| v.to_int().max(1) as usize)
+                    .unwrap_or(4096);
+                let read = match self.runtime.get_socket(&sock) {
+                    Some(socket) => socket.receive(max),
+                    None => return Some(Err(unknown_socket(&sock))),
+                };
+                match read {
+                    Ok(bytes) => {
+                        let received = !bytes.is_empty();
+                        self.assign_out(name, exprs.get(1), Value::Bytes(bytes))
+                            .map(|_| Value::Bool(received))
                     }
-                    _ => {
-                        eprintln!("Warning: unknown function '{}'", name);
-                        Ok(Value::Nil)
+                    Err(e) => Err(socket_error(format!("Failed to read from '{}'", sock), e)),
+                }
+            }
+            "socktimeout" => {
+                let sock = arg(0).to_string();
+                let ms = arg(1).to_int();
+                let timeout = (ms > 0).then(|| Duration::from_millis(ms as u64));
+                self.runtime
+                    .set_timeout(&sock, timeout)
+                    .map(|_| Value::Bool(true))
+                    .map_err(|e| socket_error(format!("Failed to set timeout on '{}'", sock), e))
+            }
+            "sockselect" => self.sockselect(&arg(0), arg(1)),
+            "http_serve" => self.http_serve(args),
+            "udpopen" => {
+                // Without an address the socket only sends, from any free port.
+                let addr = match args.len() {
+                    0 | 1 => "0.0.0.0:0".to_string(),
+                    _ => format!("{}:{}", arg(1), arg(2).to_int()),
+                };
+                Datagram::bind(&addr)
+                    .and_then(|datagram| self.runtime.set_datagram(arg(0).to_string(), datagram))
+                    .map(|_| Value::Bool(true))
+                    .map_err(|e| socket_error(format!("Failed to bind UDP socket to {}", addr), e))
+            }
+            "udpsend" => {
+                let sock = arg(0).to_string();
+                let addr = format!("{}:{}", arg(1), arg(2).to_int());
+                match self.runtime.get_datagram(&sock) {
+                    Some(datagram) => datagram
+                        .send_to(&arg(3).to_bytes(), &addr)
+                        .map(|_| Value::Bool(true))
+                        .map_err(|e| socket_error(format!("Failed to send to {}", addr), e)),
+                    None => Err(unknown_socket(&sock)),
+                }
+            }
+            "udprecv" | "udprecvbytes" => {
+                let sock = arg(0).to_string();
+                let received = match self.runtime.get_datagram(&sock) {
+                    Some(datagram) => datagram.receive(),
+                    None => return Some(Err(unknown_socket(&sock))),
+                };
+                let (bytes, from) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        return Some(Err(socket_error(
+                            format!("Failed to receive on '{}'", sock),
+                            e,
+                        )))
+                    }
+                };
+                let data = if name == "udprecvbytes" {
+                    Value::Bytes(bytes)
+                } else {
+                    Value::String(String::from_utf8_lossy(&bytes).to_string())
+                };
+                let stored =
+                    self.assign_out(name, exprs.get(1), data)
+                        .and_then(|_| match exprs.get(2) {
+                            Some(target) => {
+                                self.assign_out(name, Some(target), Value::String(from.to_string()))
+                            }
+                            None => Ok(()),
+                        });
+                stored.map(|_| Value::Bool(true))
+            }
+            "sockstatus" => Ok(Value::String(
+                self.runtime.socket_status(&arg(0).to_string()).to_string(),
+            )),
+            "sockerror" => Ok(self
+                .runtime
+                .socket_error(&arg(0).to_string())
+                .map(Value::String)
+                .unwrap_or(Value::Nil)),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn call_file_handle(
+        &mut self,
+        name: &str,
+        exprs: &[Expr],
+        args: &[Value],
+    ) -> Option<Result<Value, Error>> {
+        let arg = |idx: usize| args.get(idx).cloned().unwrap_or(Value::Nil);
+        let handle = arg(0).to_string();
+
+        let result = match name {
+            "fopen" => {
+                let path = self.resolve_include_path(&arg(1).to_string());
+                let mode = args.get(2).map(|v| v.to_string());
+                FileHandle::open(&path, mode.as_deref().unwrap_or("r"))
+                    .map(|file| {
+                        self.runtime.set_file(handle, file);
+                        Value::Bool(true)
+                    })
+                    .map_err(|e| files::file_error("open", &path, e))
+            }
+            "freadline" => {
+                let read = match self.runtime.get_file(&handle) {
+                    Some(file) => file.read_line(),
+                    None => return Some(Err(unknown_file(&handle))),
+                };
+                match read {
+                    Ok(line) => {
+                        let found = line.is_some();
+                        let text = String::from_utf8_lossy(&line.unwrap_or_default()).to_string();
+                        self.assign_out(name, exprs.get(1), Value::String(text))
+                            .map(|_| Value::Bool(found))
+                    }
+                    Err(e) => Err(handle_error("read from", &handle, e)),
+                }
+            }
+            "fwrite" => match self.runtime.get_file(&handle) {
+                Some(file) => file
+                    .write(&arg(1).to_bytes())
+                    .map(|_| Value::Bool(true))
+                    .map_err(|e| handle_error("write to", &handle, e)),
+                None => Err(unknown_file(&handle)),
+            },
+            "fseek" => {
+                let offset = arg(1).to_int();
+                let whence = args.get(2).map(|v| v.to_string());
+                let pos = match whence.as_deref() {
+                    None | Some("set") if offset >= 0 => SeekFrom::Start(offset as u64),
+                    None | Some("set") => {
+                        return Some(Err(Error::new(
+                            "file",
+                            format!("Cannot seek '{}' to negative offset {}", handle, offset),
+                        )))
+                    }
+                    Some("cur") => SeekFrom::Current(offset),
+                    Some("end") => SeekFrom::End(offset),
+                    Some(other) => {
+                        return Some(Err(Error::new(
+                            "file",
+                            format!(
+                                "fseek() expects \"set\", \"cur\" or \"end\", got '{}'",
+                                other
+                            ),
+                        )))
                     }
+                };
+                match self.runtime.get_file(&handle) {
+                    Some(file) => file
+                        .seek(pos)
+                        .map(|offset| Value::Int(offset as i64))
+                        .map_err(|e| handle_error("seek in", &handle, e)),
+                    None => Err(unknown_file(&handle)),
                 }
             }
+            "fclose" => Ok(Value::Bool(self.runtime.remove_file(&handle))),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn call_process(
+        &mut self,
+        name: &str,
+        exprs: &[Expr],
+        args: &[Value],
+    ) -> Option<Result<Value, Error>> {
+        let arg = |idx: usize| args.get(idx).cloned().unwrap_or(Value::Nil);
+        let handle = arg(0).to_string();
+
+        if name == "spawn" {
+            return Some(
+                Process::spawn(args).map(|child| Value::String(self.runtime.add_process(child))),
+            );
+        }
+        if name == "procclose" {
+            // Stopping a child that is still running, so none are left
+            // behind by accident.
+            let closed = match self.runtime.remove_process(&handle) {
+                Some(mut child) => child.kill().map(|_| true),
+                None => Ok(false),
+            };
+            return Some(
+                closed
+                    .map(Value::Bool)
+                    .map_err(|e| process_failure("stop", &handle, e)),
+            );
+        }
+        if !matches!(
+            name,
+            "procread"
+                | "procreaderr"
+                | "procreadline"
+                | "procwrite"
+                | "procclosein"
+                | "procwait"
+                | "prockill"
+                | "procpid"
+                | "procstatus"
+        ) {
+            return None;
         }
+
+        let child = match self.runtime.get_process(&handle) {
+            Some(child) => child,
+            None if name == "procstatus" => return Some(Ok(Value::String("closed".to_string()))),
+            None => {
+                return Some(Err(process::process_error(format!(
+                    "Unknown process handle '{}'",
+                    handle
+                ))))
+            }
+        };
+        // A negative or missing timeout waits as long as it takes.
+        let timeout = match args.get(if name == "procwait" { 1 } else { 2 }) {
+            Some(ms) if ms.to_int() >= 0 => Some(Duration::from_millis(ms.to_int() as u64)),
+            _ => None,
+        };
+
+        let result = match name {
+            "procread" | "procreaderr" | "procreadline" => {
+                let read = match name {
+                    "procread" => child.stdout().read(timeout),
+                    "procreaderr" => child.stderr().read(timeout),
+                    _ => child.stdout().read_line(),
+                };
+                // false only once the stream has ended; a read that times
+                // out stores an empty string.
+                let more = read.is_some();
+                let text = String::from_utf8_lossy(&read.unwrap_or_default()).to_string();
+                self.assign_out(name, exprs.get(1), Value::String(text))
+                    .map(|_| Value::Bool(more))
+            }
+            "procwrite" => child
+                .write(&arg(1).to_bytes())
+                .map(|_| Value::Bool(true))
+                .map_err(|e| process_failure("write to", &handle, e)),
+            "procclosein" => {
+                child.close_stdin();
+                Ok(Value::Bool(true))
+            }
+            "procwait" => child
+                .wait(timeout)
+                .map(|status| status.map(Value::Int).unwrap_or(Value::Bool(false)))
+                .map_err(|e| process_failure("wait for", &handle, e)),
+            "prockill" => child
+                .kill()
+                .map(|_| Value::Bool(true))
+                .map_err(|e| process_failure("kill", &handle, e)),
+            "procpid" => Ok(Value::Int(child.pid() as i64)),
+            _ => child
+                .is_running()
+                .map(|running| {
+                    Value::String(if running { "running" } else { "exited" }.to_string())
+                })
+                .map_err(|e| process_failure("check", &handle, e)),
+        };
+        Some(result)
+    }
+
+    /// A timeout of 0 polls once; a negative or missing one waits forever.
+    fn sockselect(&mut self, names: &Value, timeout: Value) -> Result<Value, Error> {
+        let names: Vec<String> = match names {
+            Value::Array(items) => items.iter().map(|v| v.to_string()).collect(),
+            other => return Err(format!("sockselect() expects an array, got '{}'", other).into()),
+        };
+        if let Some(name) = names
+            .iter()
+            .find(|name| self.runtime.socket_status(name) == "closed")
+        {
+            return Err(unknown_socket(name));
+        }
+
+        let timeout = match timeout {
+            Value::Nil => None,
+            ms if ms.to_int() < 0 => None,
+            ms => Some(Duration::from_millis(ms.to_int() as u64)),
+        };
+        let runtime = &mut self.runtime;
+        let ready = socket::select(names.len(), timeout, |idx| {
+            let name = &names[idx];
+            if let Some(listener) = runtime.get_listener(name) {
+                return listener.is_readable();
+            }
+            if let Some(datagram) = runtime.get_datagram(name) {
+                return datagram.is_readable();
+            }
+            match runtime.get_socket(name) {
+                Some(socket) => socket.is_readable(),
+                None => Ok(false),
+            }
+        })
+        .map_err(|e| socket_error("sockselect() failed".to_string(), e))?;
+
+        Ok(Value::Array(
+            ready
+                .into_iter()
+                .map(|idx| Value::String(names[idx].clone()))
+                .collect(),
+        ))
     }
+
+    /// The listener is registered like any other socket, so a handler can
+    /// stop the server with `sockclose`.
+    fn http_serve(&mut self, args: &[Value]) -> Result<Value, Error> {
+        let handler = self.expect_callable("http_serve", args.get(1))?;
+        let options = match args.get(2) {
+            Some(Value::Map(options)) => options.clone(),
+            _ => Vec::new(),
+        };
+        let option = |key: &str| map_get(&options, key);
+        let host = option("host").map(|v| v.to_string());
+        let server = option("name")
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "http".to_string());
+        let limit = option("requests").map(|v| v.to_int());
+        let max_body = option("max_body")
+            .map(|v| v.to_int().max(0) as usize)
+            .unwrap_or(http::MAX_BODY_SIZE);
+
+        let port = args.first().cloned().unwrap_or(Value::Int(80));
+        let addr = socket_address(
+            &Value::String(host.unwrap_or_else(|| "0.0.0.0".to_string())),
+            Some(&port),
+        );
+        let listener = Listener::bind(&addr)
+            .map_err(|e| Error::new("socket", format!("Failed to listen on {}: {}", addr, e)))?;
+        self.runtime.set_listener(server.clone(), listener);
+
+        let mut served = 0;
+        while limit.is_none_or(|limit| served < limit) {
+            let accepted = match self.runtime.get_listener(&server) {
+                Some(listener) => listener.accept(),
+                None => break,
+            };
+            let mut client = match accepted {
+                Ok(Some(client)) => client,
+                Ok(None) => continue,
+                Err(e) => return Err(socket_error(format!("Failed to accept on '{}'", server), e)),
+            };
+            // A stalled client must not hold up the whole server.
+            let _ = client.set_timeout(Some(http::REQUEST_TIMEOUT));
+
+            let request = match http::read_request(&mut client, max_body) {
+                Ok(request) => request,
+                Err(e) => {
+                    // Whatever was not read is dropped with the connection.
+                    let Some(status) = http::reject_status(&e) else {
+                        continue;
+                    };
+                    let _ = http::write_response(
+                        &mut client,
+                        "GET",
+                        &http::text_response(status, &format!("{}\n", e.message)),
+                    );
+                    served += 1;
+                    continue;
+                }
+            };
+            let method = match &request {
+                Value::Map(map) => map_get(map, "method").map(|v| v.to_string()),
+                _ => None,
+            }
+            .unwrap_or_default();
+
+            let response = match self.call_function(&handler, vec![request]) {
+                Ok(response) => response,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    http::text_response(500, "Internal Server Error\n")
+                }
+            };
+            if let Err(e) = http::write_response(&mut client, &method, &response) {
+                eprintln!("Error: {}", e);
+                // Nothing was sent when the handler's headers were rejected.
+                if e.kind == "http" {
+                    let _ = http::write_response(
+                        &mut client,
+                        &method,
+                        &http::text_response(500, "Internal Server Error\n"),
+                    );
+                }
+            }
+            served += 1;
+        }
+
+        self.runtime.remove_socket(&server);
+        Ok(Value::Int(served))
+    }
+
+    fn assign_out(
+        &mut self,
+        caller: &str,
+        target: Option<&Expr>,
+        value: Value,
+    ) -> Result<(), Error> {
+        match target {
+            Some(Expr::Variable(var)) => {
+                self.runtime.set_var(var.clone(), value);
+                Ok(())
+            }
+            _ => Err(format!("{}() expects a variable to store the result in", caller).into()),
+        }
+    }
+
+    /// Callbacks get the element and its index; `reduce` passes the
+    /// accumulator and the element.
+    fn call_higher_order(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+        if !matches!(name, "map" | "filter" | "reduce" | "sort_by" | "each") {
+            return None;
+        }
+
+        let items = match args.first() {
+            Some(Value::Array(items)) => items.clone(),
+            Some(other) => {
+                return Some(Err(
+                    format!("{}() expects an array, got '{}'", name, other).into()
+                ))
+            }
+            None => return Some(Err(format!("{}() expects an array argument", name).into())),
+        };
+        let callback = match self.expect_callable(name, args.get(1)) {
+            Ok(function) => function,
+            Err(e) => return Some(Err(e.into())),
+        };
+
+        Some(self.apply_callback(name, items, &callback, args.get(2).cloned()))
+    }
+
+    fn apply_callback(
+        &mut self,
+        name: &str,
+        items: Vec<Value>,
+        callback: &Rc<Function>,
+        initial: Option<Value>,
+    ) -> Result<Value, Error> {
+        match name {
+            "map" => {
+                let mut mapped = Vec::with_capacity(items.len());
+                for (idx, item) in items.into_iter().enumerate() {
+                    mapped.push(self.call_function(callback, vec![item, Value::Int(idx as i64)])?);
+                }
+                Ok(Value::Array(mapped))
+            }
+            "filter" => {
+                let mut kept = Vec::new();
+                for (idx, item) in items.into_iter().enumerate() {
+                    let keep =
+                        self.call_function(callback, vec![item.clone(), Value::Int(idx as i64)])?;
+                    if keep.is_truthy() {
+                        kept.push(item);
+                    }
+                }
+                Ok(Value::Array(kept))
+            }
+            "reduce" => {
+                let mut items = items.into_iter();
+                let mut acc = match initial {
+                    Some(value) => value,
+                    None => items.next().unwrap_or(Value::Nil),
+                };
+                for item in items {
+                    acc = self.call_function(callback, vec![acc, item])?;
+                }
+                Ok(acc)
+            }
+            "sort_by" => {
+                let mut keyed = Vec::with_capacity(items.len());
+                for (idx, item) in items.into_iter().enumerate() {
+                    let key =
+                        self.call_function(callback, vec![item.clone(), Value::Int(idx as i64)])?;
+                    keyed.push((key, item));
+                }
+                keyed.sort_by(|(a, _), (b, _)| a.sort_cmp(b));
+                Ok(Value::Array(
+                    keyed.into_iter().map(|(_, item)| item).collect(),
+                ))
+            }
+            _ => {
+                for (idx, item) in items.into_iter().enumerate() {
+                    self.call_function(callback, vec![item, Value::Int(idx as i64)])?;
+                }
+                Ok(Value::Nil)
+            }
+        }
+    }
+}
+
+/// Booleans only take part in arithmetic by mistake, so reject them.
+fn check_arithmetic(op: &BinOp, left: &Value, right: &Value) -> Result<(), Error> {
+    let concatenating = matches!(op, BinOp::Add)
+        && (matches!(left, Value::String(_)) || matches!(right, Value::String(_)));
+
+    let is_bytes = |v: &Value| matches!(v, Value::Bytes(_));
+    if (is_bytes(left) || is_bytes(right))
+        && !(matches!(op, BinOp::Add) && is_bytes(left) && is_bytes(right))
+    {
+        return Err(Error::new(
+            "type",
+            format!(
+                "Bytes can only be joined with bytes; convert with encode() or decode(): {} and {}",
+                left, right
+            ),
+        ));
+    }
+
+    if !concatenating && (matches!(left, Value::Bool(_)) || matches!(right, Value::Bool(_))) {
+        let symbol = match op {
+            BinOp::Add => "+",
+            BinOp::Subtract => "-",
+            BinOp::Multiply => "*",
+            BinOp::Divide => "/",
+            _ => "%",
+        };
+        return Err(Error::new(
+            "type",
+            format!(
+                "Cannot use a boolean in arithmetic: {} {} {}",
+                left, symbol, right
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn socket_address(host: &Value, port: Option<&Value>) -> String {
+    let host = host.to_string();
+    match port {
+        Some(port) if !host.starts_with("unix:") => format!("{}:{}", host, port.to_int()),
+        _ => host,
+    }
+}
+
+fn unknown_socket(name: &str) -> Error {
+    Error::new("socket", format!("Unknown socket '{}'", name))
+}
+
+fn unknown_file(name: &str) -> Error {
+    Error::new("file", format!("Unknown file handle '{}'", name))
+}
+
+fn process_failure(action: &str, handle: &str, error: io::Error) -> Error {
+    process::process_error(format!("Failed to {} '{}': {}", action, handle, error))
+}
+
+fn handle_error(action: &str, name: &str, error: io::Error) -> Error {
+    Error::new(
+        "file",
+        format!("Failed to {} '{}': {}", action, name, error),
+    )
 }
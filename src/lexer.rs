@@ -12,7 +12,11 @@ pub enum Token {
     // Literals
     Int(i64),
     String(String),
+    Bytes(Vec<u8>),
     Variable(String),
+    True,
+    False,
+    Nil,
 
     // Keywords
     If,
@@ -42,6 +46,10 @@ pub enum Token {
     Include,
     Function,
     Return,
+    Try,
+    Catch,
+    Finally,
+    Throw,
     And,
     Or,
     Not,
@@ -55,12 +63,14 @@ pub enum Token {
     Equals,
     EqualEqual,
     NotEqual,
+    Match,
     Less,
     LessEqual,
     Greater,
     GreaterEqual,
     Ampersand,
     Pipe,
+    QuestionQuestion,
     At,
 
     // Delimiters
@@ -72,16 +82,20 @@ pub enum Token {
     RightBracket,
     Semicolon,
     Comma,
+    Colon,
     Dot,
 
     // Special
     Newline,
+    /// A character that starts no token, reported by the parser.
+    Unexpected(char),
     Eof,
 }
 
 pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
     current: Option<char>,
+    line: usize,
 }
 
 impl<'a> Lexer<'a> {
@@ -89,16 +103,19 @@ impl<'a> Lexer<'a> {
         let mut lexer = Lexer {
             input: input.chars().peekable(),
             current: None,
+            line: 1,
         };
         lexer.advance();
         lexer
     }
 
     fn advance(&mut self) {
+        if self.current == Some('\n') {
+            self.line += 1;
+        }
         self.current = self.input.next();
     }
 
-    #[allow(dead_code)]
     fn peek(&mut self) -> Option<char> {
         self.input.peek().copied()
     }
@@ -151,6 +168,53 @@ impl<'a> Lexer<'a> {
         result
     }
 
+    /// Read a `b"..."` literal. Besides the string escapes it accepts `\xNN`
+    /// for arbitrary bytes; other characters are stored as UTF-8.
+    fn read_bytes(&mut self, quote: char) -> Vec<u8> {
+        let mut result = Vec::new();
+        self.advance();
+
+        while let Some(ch) = self.current {
+            if ch == quote {
+                self.advance();
+                break;
+            } else if ch == '\\' {
+                self.advance();
+                match self.current {
+                    Some('x') => {
+                        let mut byte = 0;
+                        for _ in 0..2 {
+                            match self.peek().and_then(|c| c.to_digit(16)) {
+                                Some(digit) => {
+                                    self.advance();
+                                    byte = byte * 16 + digit as u8;
+                                }
+                                None => break,
+                            }
+                        }
+                        result.push(byte);
+                    }
+                    Some('n') => result.push(b'\n'),
+                    Some('t') => result.push(b'\t'),
+                    Some('r') => result.push(b'\r'),
+                    Some('0') => result.push(0),
+                    Some(c) => {
+                        let mut buf = [0; 4];
+                        result.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    }
+                    None => break,
+                }
+                self.advance();
+            } else {
+                let mut buf = [0; 4];
+                result.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                self.advance();
+            }
+        }
+
+        result
+    }
+
     fn read_number(&mut self) -> i64 {
         let mut num_str = String::new();
         while let Some(ch) = self.current {
@@ -219,6 +283,9 @@ impl<'a> Lexer<'a> {
                 if self.current == Some('=') {
                     self.advance();
                     Token::EqualEqual
+                } else if self.current == Some('~') {
+                    self.advance();
+                    Token::Match
                 } else {
                     Token::Equals
                 }
@@ -268,6 +335,15 @@ impl<'a> Lexer<'a> {
                     Token::Pipe
                 }
             }
+            Some('?') => {
+                self.advance();
+                if self.current == Some('?') {
+                    self.advance();
+                    Token::QuestionQuestion
+                } else {
+                    Token::Unexpected('?')
+                }
+            }
             Some('$') => {
                 self.advance();
                 let name = self.read_identifier();
@@ -309,6 +385,10 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 Token::Comma
             }
+            Some(':') => {
+                self.advance();
+                Token::Colon
+            }
             Some('.') => {
                 self.advance();
                 Token::Dot
@@ -318,7 +398,15 @@ impl<'a> Lexer<'a> {
             Some(ch) if ch.is_ascii_digit() => Token::Int(self.read_number()),
             Some(ch) if ch.is_alphabetic() || ch == '_' => {
                 let ident = self.read_identifier();
+                if ident == "b" {
+                    if let Some(quote @ ('"' | '\'')) = self.current {
+                        return Token::Bytes(self.read_bytes(quote));
+                    }
+                }
                 match ident.as_str() {
+                    "true" => Token::True,
+                    "false" => Token::False,
+                    "nil" => Token::Nil,
                     "if" => Token::If,
                     "elseif" => Token::Elseif,
                     "else" => Token::Else,
@@ -346,27 +434,33 @@ impl<'a> Lexer<'a> {
                     "include" => Token::Include,
                     "function" | "func" => Token::Function,
                     "return" => Token::Return,
+                    "try" => Token::Try,
+                    "catch" => Token::Catch,
+                    "finally" => Token::Finally,
+                    "throw" => Token::Throw,
                     "AND" => Token::And,
                     "OR" => Token::Or,
                     _ => Token::Variable(ident),
                 }
             }
-            Some(_) => {
+            Some(ch) => {
                 self.advance();
-                self.next_token()
+                Token::Unexpected(ch)
             }
         }
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    /// Tokenize the whole input, pairing each token with its source line.
+    pub fn tokenize(&mut self) -> Vec<(Token, usize)> {
         let mut tokens = Vec::new();
         loop {
+            let line = self.line;
             let token = self.next_token();
             if token == Token::Eof {
-                tokens.push(token);
+                tokens.push((token, line));
                 break;
             }
-            tokens.push(token);
+            tokens.push((token, line));
         }
         tokens
     }
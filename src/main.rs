@@ -4,10 +4,22 @@
 // License: MPL 2.0
 // SPDX-License-Identifier: MPL-2.0
 
+mod arrays;
+mod bytes;
+mod csv;
+mod error;
+mod files;
+mod http;
 mod interpreter;
+mod json;
 mod lexer;
 mod parser;
+mod process;
+mod regexp;
 mod runtime;
+mod socket;
+mod template;
+mod tls;
 mod value;
 
 use interpreter::Interpreter;
@@ -34,7 +46,7 @@ fn execute_file(path: &str) -> Result<(), String> {
     let content = fs::read_to_string(path).map_err(|e| format!("Failed to read file: {}", e))?;
 
     let mut parser = Parser::new(&content);
-    let statements = parser.parse();
+    let statements = parser.parse().map_err(|e| e.to_string())?;
 
     let mut interpreter = Interpreter::new();
     let absolute_path = {
@@ -91,7 +103,13 @@ fn run_repl() {
         }
 
         let mut parser = Parser::new(trimmed);
-        let statements = parser.parse();
+        let statements = match parser.parse() {
+            Ok(statements) => statements,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
 
         let mut interpreter = Interpreter::new();
         if let Err(e) = interpreter.execute(statements) {
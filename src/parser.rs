@@ -4,6 +4,7 @@
 // License: MPL 2.0
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::error::Error;
 use crate::lexer::{Lexer, Token};
 use std::collections::VecDeque;
 
@@ -11,6 +12,9 @@ use std::collections::VecDeque;
 pub enum Expr {
     Int(i64),
     String(String),
+    Bytes(Vec<u8>),
+    Bool(bool),
+    Nil,
     Variable(String),
     Binary {
         left: Box<Expr>,
@@ -28,9 +32,13 @@ pub enum Expr {
     },
     FunctionCall {
         name: String,
-        #[allow(dead_code)]
         args: Vec<Expr>,
     },
+    Lambda {
+        params: Vec<String>,
+        body: Vec<Statement>,
+    },
+    Map(Vec<(Expr, Expr)>),
 }
 
 #[derive(Debug, Clone)]
@@ -46,8 +54,10 @@ pub enum BinOp {
     LessEqual,
     Greater,
     GreaterEqual,
+    Matches,
     And,
     Or,
+    Coalesce,
 }
 
 #[derive(Debug, Clone)]
@@ -106,10 +116,23 @@ pub enum Statement {
         array: String,
         value: Expr,
     },
+    Sort {
+        array: String,
+    },
+    Reverse {
+        array: String,
+    },
+    Unique {
+        array: String,
+    },
+    Concat {
+        array: String,
+        value: Expr,
+    },
     Sockopen {
         name: String,
         host: Expr,
-        port: Expr,
+        port: Option<Expr>,
     },
     Sockclose {
         name: String,
@@ -121,34 +144,54 @@ pub enum Statement {
     Sockread {
         name: String,
         var: String,
+        max: Option<Expr>,
     },
     Include {
         path: String,
     },
     FunctionDef {
         name: String,
+        /// `None` for the `func name { ... }` block form without a
+        /// parameter list.
+        params: Option<Vec<String>>,
         body: Vec<Statement>,
     },
     FunctionCall {
         name: String,
-        #[allow(dead_code)]
         args: Vec<Expr>,
     },
     Return {
         value: Option<Expr>,
     },
+    Try {
+        body: Vec<Statement>,
+        catch_var: Option<String>,
+        catch_body: Option<Vec<Statement>>,
+        finally_body: Option<Vec<Statement>>,
+    },
+    Throw {
+        value: Expr,
+    },
+    /// Marks the source line of the statement that follows, for error reporting.
+    Line {
+        line: usize,
+    },
 }
 
 pub struct Parser {
     tokens: VecDeque<Token>,
+    lines: VecDeque<usize>,
+    line: usize,
 }
 
 impl Parser {
     pub fn new(input: &str) -> Self {
         let mut lexer = Lexer::new(input);
-        let tokens = lexer.tokenize();
+        let (tokens, lines) = lexer.tokenize().into_iter().unzip();
         Parser {
-            tokens: tokens.into_iter().collect(),
+            tokens,
+            lines,
+            line: 1,
         }
     }
 
@@ -156,8 +199,21 @@ impl Parser {
         self.tokens.front().unwrap_or(&Token::Eof)
     }
 
+    fn current_line(&self) -> usize {
+        self.lines.front().copied().unwrap_or(self.line)
+    }
+
     fn advance(&mut self) {
         self.tokens.pop_front();
+        if let Some(line) = self.lines.pop_front() {
+            self.line = line;
+        }
+    }
+
+    /// Put back a token that was consumed while looking ahead.
+    fn push_front(&mut self, token: Token) {
+        self.tokens.push_front(token);
+        self.lines.push_front(self.line);
     }
 
     fn expect(&mut self, expected: Token) -> bool {
@@ -175,7 +231,23 @@ impl Parser {
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Statement> {
+    /// Parse the whole program. A character the lexer could not make sense
+    /// of is reported as a `syntax` error at its line.
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Error> {
+        let unexpected =
+            self.tokens
+                .iter()
+                .zip(&self.lines)
+                .find_map(|(token, line)| match token {
+                    Token::Unexpected(ch) => Some((*ch, *line)),
+                    _ => None,
+                });
+        if let Some((ch, line)) = unexpected {
+            return Err(
+                Error::new("syntax", format!("Unexpected character '{}'", ch)).at_line(line),
+            );
+        }
+
         let mut statements = Vec::new();
         self.skip_newlines();
 
@@ -185,13 +257,20 @@ impl Parser {
                 break;
             }
 
-            if let Some(stmt) = self.parse_statement() {
-                statements.push(stmt);
-            }
+            self.parse_statement_into(&mut statements);
             self.skip_newlines();
         }
 
-        statements
+        Ok(statements)
+    }
+
+    /// Parse one statement, preceded by a `Line` marker for its source line.
+    fn parse_statement_into(&mut self, statements: &mut Vec<Statement>) {
+        let line = self.current_line();
+        if let Some(stmt) = self.parse_statement() {
+            statements.push(Statement::Line { line });
+            statements.push(stmt);
+        }
     }
 
     fn parse_statement(&mut self) -> Option<Statement> {
@@ -215,8 +294,10 @@ impl Parser {
             Token::Include => self.parse_include(),
             Token::Function => self.parse_function_def(),
             Token::Return => self.parse_return(),
+            Token::Try => self.parse_try(),
+            Token::Throw => self.parse_throw(),
             Token::Sleep => self.parse_sleep(),
-            Token::Elseif | Token::Else => {
+            Token::Elseif | Token::Else | Token::Catch | Token::Finally => {
                 // These should have been consumed by the previous if statement
                 // If we see them here, skip them to avoid treating them as separate statements
                 self.advance();
@@ -226,13 +307,34 @@ impl Parser {
                 let saved_name = name.clone();
                 self.advance();
 
+                // `sort $list` and friends modify an array in place. The names
+                // are not reserved, so `sort($list)` stays an ordinary call.
+                if let Token::Variable(array) = self.current() {
+                    let array = array.clone();
+                    match saved_name.as_str() {
+                        "sort" | "reverse" | "unique" => {
+                            return self.parse_array_in_place(&saved_name, array)
+                        }
+                        "concat" => return self.parse_concat(array),
+                        _ => {}
+                    }
+                }
+
                 if self.current() == &Token::LeftBrace {
-                    self.tokens.push_front(Token::LeftBrace);
-                    self.tokens.push_front(Token::Variable(saved_name.clone()));
+                    self.push_front(Token::LeftBrace);
+                    self.push_front(Token::Variable(saved_name.clone()));
                     self.parse_function_call()
+                } else if self.current() == &Token::LeftParen {
+                    self.advance();
+                    let args = self.parse_call_args();
+                    self.skip_statement_end();
+                    Some(Statement::FunctionCall {
+                        name: saved_name,
+                        args,
+                    })
                 } else if self.current() == &Token::Equals || self.current() == &Token::LeftBracket
                 {
-                    self.tokens.push_front(Token::Variable(saved_name.clone()));
+                    self.push_front(Token::Variable(saved_name.clone()));
                     self.parse_assignment()
                 } else if self.current() == &Token::Semicolon
                     || self.current() == &Token::Newline
@@ -240,7 +342,7 @@ impl Parser {
                 {
                     self.parse_function_call_simple(saved_name)
                 } else {
-                    self.tokens.push_front(Token::Variable(saved_name.clone()));
+                    self.push_front(Token::Variable(saved_name.clone()));
                     self.parse_assignment()
                 }
             }
@@ -341,9 +443,7 @@ impl Parser {
                 break;
             }
 
-            if let Some(stmt) = self.parse_statement() {
-                statements.push(stmt);
-            }
+            self.parse_statement_into(&mut statements);
             self.skip_newlines();
         }
 
@@ -581,6 +681,34 @@ impl Parser {
         None
     }
 
+    /// Parse the rest of `sort $list`, `reverse $list` or `unique $list`
+    /// once the name and the array variable have been seen.
+    fn parse_array_in_place(&mut self, op: &str, array: String) -> Option<Statement> {
+        self.advance();
+        self.skip_statement_end();
+
+        match op {
+            "sort" => Some(Statement::Sort { array }),
+            "reverse" => Some(Statement::Reverse { array }),
+            _ => Some(Statement::Unique { array }),
+        }
+    }
+
+    /// Parse the rest of `concat $list, value` once the array variable has
+    /// been seen.
+    fn parse_concat(&mut self, array: String) -> Option<Statement> {
+        self.advance();
+
+        if !self.expect(Token::Comma) {
+            return None;
+        }
+
+        let value = self.parse_expr();
+        self.skip_statement_end();
+
+        Some(Statement::Concat { array, value })
+    }
+
     fn parse_sockopen(&mut self) -> Option<Statement> {
         self.advance();
 
@@ -602,11 +730,13 @@ impl Parser {
 
         let host = self.parse_expr();
 
-        if !self.expect(Token::Comma) {
-            return None;
-        }
-
-        let port = self.parse_expr();
+        // The port is omitted for Unix sockets: sockopen("name", "unix:/path")
+        let port = if self.current() == &Token::Comma {
+            self.advance();
+            Some(self.parse_expr())
+        } else {
+            None
+        };
 
         if !self.expect(Token::RightParen) {
             return None;
@@ -698,13 +828,19 @@ impl Parser {
             return None;
         };
 
+        let max = if self.expect(Token::Comma) {
+            Some(self.parse_expr())
+        } else {
+            None
+        };
+
         if !self.expect(Token::RightParen) {
             return None;
         }
 
         self.skip_statement_end();
 
-        Some(Statement::Sockread { name, var })
+        Some(Statement::Sockread { name, var, max })
     }
 
     fn parse_include(&mut self) -> Option<Statement> {
@@ -755,10 +891,11 @@ impl Parser {
             return None;
         };
 
-        if self.current() == &Token::LeftParen {
-            self.advance();
-            self.expect(Token::RightParen);
-        }
+        let params = if self.expect(Token::LeftParen) {
+            Some(self.parse_params())
+        } else {
+            None
+        };
 
         if !self.expect(Token::LeftBrace) {
             return None;
@@ -766,7 +903,23 @@ impl Parser {
 
         let body = self.parse_block();
 
-        Some(Statement::FunctionDef { name, body })
+        Some(Statement::FunctionDef { name, params, body })
+    }
+
+    /// Parse `$a, $b)` after the opening parenthesis of a function signature.
+    fn parse_params(&mut self) -> Vec<String> {
+        let mut params = Vec::new();
+
+        while let Token::Variable(param) = self.current() {
+            params.push(param.clone());
+            self.advance();
+            if !self.expect(Token::Comma) {
+                break;
+            }
+        }
+
+        self.expect(Token::RightParen);
+        params
     }
 
     fn parse_return(&mut self) -> Option<Statement> {
@@ -786,6 +939,64 @@ impl Parser {
         Some(Statement::Return { value })
     }
 
+    fn parse_try(&mut self) -> Option<Statement> {
+        self.advance();
+
+        if !self.expect(Token::LeftBrace) {
+            return None;
+        }
+
+        let body = self.parse_block();
+        let mut catch_var = None;
+        let mut catch_body = None;
+        let mut finally_body = None;
+
+        self.skip_newlines();
+        if self.current() == &Token::Catch {
+            self.advance();
+
+            if self.expect(Token::LeftParen) {
+                if let Token::Variable(name) = self.current() {
+                    catch_var = Some(name.clone());
+                    self.advance();
+                }
+                if !self.expect(Token::RightParen) {
+                    return None;
+                }
+            }
+
+            if !self.expect(Token::LeftBrace) {
+                return None;
+            }
+            catch_body = Some(self.parse_block());
+            self.skip_newlines();
+        }
+
+        if self.current() == &Token::Finally {
+            self.advance();
+            if !self.expect(Token::LeftBrace) {
+                return None;
+            }
+            finally_body = Some(self.parse_block());
+        }
+
+        Some(Statement::Try {
+            body,
+            catch_var,
+            catch_body,
+            finally_body,
+        })
+    }
+
+    fn parse_throw(&mut self) -> Option<Statement> {
+        self.advance();
+
+        let value = self.parse_expr();
+        self.skip_statement_end();
+
+        Some(Statement::Throw { value })
+    }
+
     fn parse_function_call(&mut self) -> Option<Statement> {
         if let Token::Variable(name) = self.current() {
             let fname = name.clone();
@@ -816,8 +1027,40 @@ impl Parser {
         Some(Statement::FunctionCall { name, args: vec![] })
     }
 
+    /// Parse a comma separated argument list after the opening parenthesis,
+    /// consuming the closing one.
+    fn parse_call_args(&mut self) -> Vec<Expr> {
+        let mut args = Vec::new();
+
+        while self.current() != &Token::RightParen && self.current() != &Token::Eof {
+            args.push(self.parse_expr());
+            if self.current() == &Token::Comma {
+                self.advance();
+            }
+        }
+
+        self.expect(Token::RightParen);
+        args
+    }
+
     fn parse_expr(&mut self) -> Expr {
-        self.parse_or()
+        self.parse_coalesce()
+    }
+
+    fn parse_coalesce(&mut self) -> Expr {
+        let mut left = self.parse_or();
+
+        while self.current() == &Token::QuestionQuestion {
+            self.advance();
+            let right = self.parse_or();
+            left = Expr::Binary {
+                left: Box::new(left),
+                op: BinOp::Coalesce,
+                right: Box::new(right),
+            };
+        }
+
+        left
     }
 
     fn parse_or(&mut self) -> Expr {
@@ -896,6 +1139,7 @@ impl Parser {
             Token::LessEqual => Some(BinOp::LessEqual),
             Token::Greater => Some(BinOp::Greater),
             Token::GreaterEqual => Some(BinOp::GreaterEqual),
+            Token::Match => Some(BinOp::Matches),
             _ => None,
         } {
             self.advance();
@@ -974,19 +1218,14 @@ impl Parser {
     fn parse_postfix(&mut self) -> Expr {
         let mut expr = self.parse_primary();
 
-        loop {
-            match self.current() {
-                Token::LeftBracket => {
-                    self.advance();
-                    let index = self.parse_expr();
-                    self.expect(Token::RightBracket);
-                    expr = Expr::Index {
-                        expr: Box::new(expr),
-                        index: Box::new(index),
-                    };
-                }
-                _ => break,
-            }
+        while self.current() == &Token::LeftBracket {
+            self.advance();
+            let index = self.parse_expr();
+            self.expect(Token::RightBracket);
+            expr = Expr::Index {
+                expr: Box::new(expr),
+                index: Box::new(index),
+            };
         }
 
         expr
@@ -1002,6 +1241,22 @@ impl Parser {
                 self.advance();
                 Expr::String(s)
             }
+            Token::Bytes(bytes) => {
+                self.advance();
+                Expr::Bytes(bytes)
+            }
+            Token::True => {
+                self.advance();
+                Expr::Bool(true)
+            }
+            Token::False => {
+                self.advance();
+                Expr::Bool(false)
+            }
+            Token::Nil => {
+                self.advance();
+                Expr::Nil
+            }
             Token::Len => {
                 self.advance();
                 if self.expect(Token::LeftParen) {
@@ -1080,22 +1335,39 @@ impl Parser {
                     Expr::Int(0)
                 }
             }
+            Token::Sockstatus => {
+                self.advance();
+                if self.expect(Token::LeftParen) {
+                    Expr::FunctionCall {
+                        name: "sockstatus".to_string(),
+                        args: self.parse_call_args(),
+                    }
+                } else {
+                    Expr::String(String::new())
+                }
+            }
+            Token::Function => {
+                self.advance();
+                let params = if self.expect(Token::LeftParen) {
+                    self.parse_params()
+                } else {
+                    Vec::new()
+                };
+
+                let body = if self.expect(Token::LeftBrace) {
+                    self.parse_block()
+                } else {
+                    Vec::new()
+                };
+
+                Expr::Lambda { params, body }
+            }
             Token::Variable(name) => {
                 self.advance();
 
                 if self.current() == &Token::LeftParen {
                     self.advance();
-                    let mut args = Vec::new();
-
-                    while self.current() != &Token::RightParen && self.current() != &Token::Eof {
-                        args.push(self.parse_expr());
-                        if self.current() == &Token::Comma {
-                            self.advance();
-                        }
-                    }
-
-                    self.expect(Token::RightParen);
-
+                    let args = self.parse_call_args();
                     Expr::FunctionCall { name, args }
                 } else {
                     Expr::Variable(name)
@@ -1107,6 +1379,27 @@ impl Parser {
                 self.expect(Token::RightParen);
                 expr
             }
+            Token::LeftBrace => {
+                self.advance();
+                let mut entries = Vec::new();
+
+                self.skip_newlines();
+                while self.current() != &Token::RightBrace && self.current() != &Token::Eof {
+                    let key = self.parse_expr();
+                    if !self.expect(Token::Colon) {
+                        break;
+                    }
+                    self.skip_newlines();
+                    let value = self.parse_expr();
+                    entries.push((key, value));
+
+                    self.expect(Token::Comma);
+                    self.skip_newlines();
+                }
+
+                self.expect(Token::RightBrace);
+                Expr::Map(entries)
+            }
             Token::LeftBracket => {
                 self.advance();
                 let mut elements = Vec::new();
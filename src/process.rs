@@ -0,0 +1,555 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::value::{map_get, Value};
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const READ_CHUNK: usize = 4096;
+
+pub fn call(name: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+    let arg = |idx: usize| args.get(idx).cloned().unwrap_or(Value::Nil);
+    let result = match name {
+        "exec" => {
+            let program = arg(0).to_string();
+            string_list("exec", &arg(1)).and_then(|argv| {
+                let mut command = Command::new(&program);
+                command.args(argv);
+                execute(command, &program, &arg(2))
+            })
+        }
+        "run" => {
+            let line = arg(0).to_string();
+            execute(shell_command(&line), &line, &arg(1))
+        }
+        "pipe" => pipeline(&arg(0), &arg(1)),
+        _ => return None,
+    };
+    Some(result)
+}
+
+pub fn shell_command(line: &str) -> Command {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(line);
+    command
+}
+
+pub fn process_error(message: impl Into<String>) -> Error {
+    Error::new("process", message)
+}
+
+fn string_list(caller: &str, value: &Value) -> Result<Vec<String>, Error> {
+    match value {
+        Value::Array(items) => Ok(items.iter().map(|v| v.to_string()).collect()),
+        Value::Nil => Ok(Vec::new()),
+        other => Err(process_error(format!(
+            "{}() expects an array of arguments, got '{}'",
+            caller, other
+        ))),
+    }
+}
+
+/// Options that cannot be applied to the `Command` itself.
+struct Options {
+    stdin: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+    /// `"pipe"`, `"stdout"` or `"inherit"`.
+    stderr: String,
+}
+
+/// An `env` entry set to nil removes that variable from the child.
+fn configure(command: &mut Command, options: &Value) -> Result<Options, Error> {
+    let options = match options {
+        Value::Map(map) => map.as_slice(),
+        Value::Nil => &[],
+        other => {
+            return Err(process_error(format!(
+                "Process options must be a map, got '{}'",
+                other
+            )))
+        }
+    };
+    let option = |key: &str| map_get(options, key).filter(|v| !matches!(v, Value::Nil));
+
+    if let Some(dir) = option("cwd") {
+        command.current_dir(dir.to_string());
+    }
+    match option("env") {
+        Some(Value::Map(vars)) => {
+            for (name, value) in vars {
+                match value {
+                    Value::Nil => command.env_remove(name),
+                    value => command.env(name, value.to_string()),
+                };
+            }
+        }
+        Some(other) => {
+            return Err(process_error(format!(
+                "Process option 'env' must be a map, got '{}'",
+                other
+            )))
+        }
+        None => {}
+    }
+
+    Ok(Options {
+        stdin: option("stdin").map(|v| v.to_bytes()),
+        timeout: option("timeout")
+            .map(|ms| ms.to_int())
+            .filter(|&ms| ms > 0)
+            .map(|ms| Duration::from_millis(ms as u64)),
+        stderr: option("stderr")
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "pipe".to_string()),
+    })
+}
+
+/// A non-zero status is not an error; running past the timeout is.
+fn execute(command: Command, label: &str, options: &Value) -> Result<Value, Error> {
+    let (stdout, stderr, statuses) = run_pipeline(vec![command], label, options)?;
+    Ok(Value::Map(vec![
+        ("stdout".to_string(), Value::String(stdout)),
+        ("stderr".to_string(), Value::String(stderr)),
+        ("status".to_string(), Value::Int(statuses[0])),
+    ]))
+}
+
+/// Commands are arrays of program and arguments, or strings split into
+/// words; no shell is involved.
+fn pipeline(commands: &Value, options: &Value) -> Result<Value, Error> {
+    let stages = match commands {
+        Value::Array(stages) if !stages.is_empty() => stages,
+        other => {
+            return Err(process_error(format!(
+                "pipe() expects a non-empty array of commands, got '{}'",
+                other
+            )))
+        }
+    };
+
+    let mut labels = Vec::new();
+    let mut built = Vec::new();
+    for stage in stages {
+        let words = match stage {
+            Value::Array(_) => string_list("pipe", stage)?,
+            other => split_words(&other.to_string())?,
+        };
+        let (program, args) = words
+            .split_first()
+            .ok_or_else(|| process_error("pipe() got an empty command"))?;
+        let mut command = Command::new(program);
+        command.args(args);
+        labels.push(words.join(" "));
+        built.push(command);
+    }
+
+    let (stdout, stderr, statuses) = run_pipeline(built, &labels.join(" | "), options)?;
+    let last = statuses.last().copied().unwrap_or(-1);
+    Ok(Value::Map(vec![
+        ("stdout".to_string(), Value::String(stdout)),
+        ("stderr".to_string(), Value::String(stderr)),
+        ("status".to_string(), Value::Int(last)),
+        (
+            "statuses".to_string(),
+            Value::Array(statuses.into_iter().map(Value::Int).collect()),
+        ),
+    ]))
+}
+
+fn run_pipeline(
+    mut commands: Vec<Command>,
+    label: &str,
+    options: &Value,
+) -> Result<(String, String, Vec<i64>), Error> {
+    // Every stage runs with the same directory and environment.
+    let mut settings = None;
+    for command in commands.iter_mut() {
+        settings = Some(configure(command, options)?);
+    }
+    let options = settings.expect("pipeline has at least one command");
+    let start_error = |e: io::Error| process_error(format!("Failed to run '{}': {}", label, e));
+
+    let mut children: Vec<Child> = Vec::new();
+    let mut errors = Vec::new();
+    for command in commands.iter_mut() {
+        let stdin = match children.last_mut().and_then(|child| child.stdout.take()) {
+            Some(previous) => Stdio::from(previous),
+            None if options.stdin.is_some() => Stdio::piped(),
+            None => Stdio::null(),
+        };
+        let spawned = command
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                for mut started in children {
+                    let _ = started.kill();
+                    let _ = started.wait();
+                }
+                return Err(start_error(e));
+            }
+        };
+        errors.push(drain(child.stderr.take()));
+        children.push(child);
+    }
+
+    // Feed stdin and drain the outputs on their own threads, so a child
+    // blocked on a full pipe can never deadlock against us.
+    if let (Some(mut pipe), Some(data)) = (children[0].stdin.take(), options.stdin) {
+        thread::spawn(move || pipe.write_all(&data));
+    }
+    let stdout = drain(children.last_mut().and_then(|child| child.stdout.take()));
+
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let mut statuses = Vec::new();
+    let mut timed_out = false;
+    for child in children.iter_mut() {
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        match wait_timeout(child, remaining) {
+            Ok(Some(status)) => statuses.push(exit_code(status)),
+            Ok(None) => {
+                timed_out = true;
+                break;
+            }
+            Err(e) => {
+                return Err(process_error(format!(
+                    "Failed to wait for '{}': {}",
+                    label, e
+                )))
+            }
+        }
+    }
+    if timed_out {
+        for child in children.iter_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        let ms = options.timeout.map(|t| t.as_millis()).unwrap_or_default();
+        return Err(Error::new(
+            "timeout",
+            format!("'{}' timed out after {} ms", label, ms),
+        ));
+    }
+
+    let stderr = errors.into_iter().map(collect).collect::<Vec<_>>().concat();
+    Ok((collect(stdout), stderr, statuses))
+}
+
+/// Single quotes keep everything literally and double quotes allow `\"`
+/// and `\\`. There is no shell to expand `$` or `*`.
+fn split_words(line: &str) -> Result<Vec<String>, Error> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(unclosed_quote(line)),
+                    }
+                }
+            }
+            '"' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err(unclosed_quote(line)),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(unclosed_quote(line)),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+fn unclosed_quote(line: &str) -> Error {
+    process_error(format!("Unclosed quote in command '{}'", line))
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut data = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut data);
+        }
+        data
+    })
+}
+
+fn collect(reader: JoinHandle<Vec<u8>>) -> String {
+    String::from_utf8_lossy(&reader.join().unwrap_or_default()).to_string()
+}
+
+/// `None` means it is still running when the time is up.
+pub fn wait_timeout(
+    child: &mut Child,
+    timeout: Option<Duration>,
+) -> io::Result<Option<ExitStatus>> {
+    let deadline = match timeout {
+        Some(timeout) => Instant::now() + timeout,
+        None => return child.wait().map(Some),
+    };
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// A child killed by a signal gives 128 plus the signal, as shells report.
+pub fn exit_code(status: ExitStatus) -> i64 {
+    if let Some(code) = status.code() {
+        return code as i64;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal as i64;
+        }
+    }
+    -1
+}
+
+/// Output is read on background threads so the script can poll it.
+pub struct Process {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: Output,
+    stderr: Output,
+    status: Option<ExitStatus>,
+}
+
+impl Process {
+    pub fn spawn(args: &[Value]) -> Result<Process, Error> {
+        let program = args.first().map(|v| v.to_string()).unwrap_or_default();
+        let mut command = Command::new(&program);
+        command.args(string_list("spawn", args.get(1).unwrap_or(&Value::Nil))?);
+        let options = configure(&mut command, args.get(2).unwrap_or(&Value::Nil))?;
+
+        let stderr = match options.stderr.as_str() {
+            "pipe" | "stdout" => Stdio::piped(),
+            "inherit" => Stdio::inherit(),
+            other => {
+                return Err(process_error(format!(
+                    "spawn() option 'stderr' must be \"pipe\", \"stdout\" or \"inherit\", got '{}'",
+                    other
+                )))
+            }
+        };
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(stderr)
+            .spawn()
+            .map_err(|e| process_error(format!("Failed to start '{}': {}", program, e)))?;
+
+        let (sender, receiver) = mpsc::channel();
+        if let Some(pipe) = child.stdout.take() {
+            forward(pipe, sender.clone());
+        }
+        let stderr = match (child.stderr.take(), options.stderr.as_str()) {
+            (Some(pipe), "stdout") => {
+                forward(pipe, sender);
+                Output::closed()
+            }
+            (Some(pipe), _) => {
+                let (errors, received) = mpsc::channel();
+                forward(pipe, errors);
+                Output::new(received)
+            }
+            (None, _) => Output::closed(),
+        };
+
+        Ok(Process {
+            stdin: child.stdin.take(),
+            child,
+            stdout: Output::new(receiver),
+            stderr,
+            status: None,
+        })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn stdout(&mut self) -> &mut Output {
+        &mut self.stdout
+    }
+
+    pub fn stderr(&mut self) -> &mut Output {
+        &mut self.stderr
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.write_all(data).and_then(|_| stdin.flush()),
+            None => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "stdin has been closed",
+            )),
+        }
+    }
+
+    pub fn close_stdin(&mut self) {
+        self.stdin = None;
+    }
+
+    /// `None` if the child is still running.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<Option<i64>> {
+        if self.status.is_none() {
+            self.status = wait_timeout(&mut self.child, timeout)?;
+        }
+        Ok(self.status.map(exit_code))
+    }
+
+    pub fn is_running(&mut self) -> io::Result<bool> {
+        Ok(self.wait(Some(Duration::ZERO))?.is_none())
+    }
+
+    pub fn kill(&mut self) -> io::Result<()> {
+        if self.status.is_none() {
+            self.child.kill()?;
+            self.status = Some(self.child.wait()?);
+        }
+        Ok(())
+    }
+}
+
+fn forward<R: Read + Send + 'static>(mut pipe: R, sender: Sender<Vec<u8>>) {
+    thread::spawn(move || {
+        let mut chunk = vec![0; READ_CHUNK];
+        loop {
+            match pipe.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if sender.send(chunk[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+pub struct Output {
+    chunks: Option<Receiver<Vec<u8>>>,
+    buffer: Vec<u8>,
+}
+
+impl Output {
+    fn new(chunks: Receiver<Vec<u8>>) -> Self {
+        Output {
+            chunks: Some(chunks),
+            buffer: Vec::new(),
+        }
+    }
+
+    fn closed() -> Self {
+        Output {
+            chunks: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// `None` means the stream has ended.
+    pub fn read(&mut self, timeout: Option<Duration>) -> Option<Vec<u8>> {
+        if self.buffer.is_empty() {
+            let deadline = timeout.map(|t| Instant::now() + t);
+            self.fill(deadline);
+        }
+        while self.fill(Some(Instant::now())) {}
+
+        if self.buffer.is_empty() && self.chunks.is_none() {
+            return None;
+        }
+        Some(std::mem::take(&mut self.buffer))
+    }
+
+    /// `None` means the stream ended.
+    pub fn read_line(&mut self) -> Option<Vec<u8>> {
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+                let mut line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Some(line);
+            }
+            if !self.fill(None) {
+                if self.buffer.is_empty() {
+                    return None;
+                }
+                return Some(std::mem::take(&mut self.buffer));
+            }
+        }
+    }
+
+    /// False on timeout or once the stream has ended.
+    fn fill(&mut self, deadline: Option<Instant>) -> bool {
+        let chunks = match &self.chunks {
+            Some(chunks) => chunks,
+            None => return false,
+        };
+        let received = match deadline {
+            None => chunks.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(deadline) => {
+                chunks.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+        };
+        match received {
+            Ok(chunk) => {
+                self.buffer.extend_from_slice(&chunk);
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => {
+                self.chunks = None;
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::value::{map_insert, Map, Value};
+use regex::{Captures, Regex};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Compiled patterns kept before the cache is emptied and starts over.
+const CACHE_SIZE: usize = 64;
+
+thread_local! {
+    /// Patterns used in loops are compiled once rather than on every call.
+    static CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+pub fn call(name: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+    let arg = |idx: usize| args.get(idx).map(text).unwrap_or_default();
+
+    let result = match name {
+        "match" => compile(&arg(0)).map(|re| match re.captures(&arg(1)) {
+            Some(caps) => captures(&re, &caps),
+            None => Value::Nil,
+        }),
+        "match_all" => compile(&arg(0)).map(|re| {
+            let text = arg(1);
+            Value::Array(
+                re.captures_iter(&text)
+                    .map(|caps| captures(&re, &caps))
+                    .collect(),
+            )
+        }),
+        "regex_replace" => compile(&arg(0))
+            .map(|re| Value::String(re.replace_all(&arg(2), arg(1).as_str()).into_owned())),
+        "regex_split" => compile(&arg(0)).map(|re| {
+            Value::Array(
+                re.split(&arg(1))
+                    .map(|part| Value::String(part.to_string()))
+                    .collect(),
+            )
+        }),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Whether `pattern` matches anywhere in `text`, for the `=~` operator.
+pub fn is_match(text: &Value, pattern: &Value) -> Result<bool, Error> {
+    compile(&self::text(pattern)).map(|re| re.is_match(&self::text(text)))
+}
+
+/// Bytes are matched as the text they hold rather than as a `b"..."`
+/// literal.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Bytes(bytes) => String::from_utf8_lossy(bytes).to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, Error> {
+    CACHE.with(|cache| {
+        if let Some(re) = cache.borrow().get(pattern) {
+            return Ok(re.clone());
+        }
+        let re = Regex::new(pattern).map_err(|e| {
+            Error::new(
+                "regex",
+                format!("Invalid regular expression '{}': {}", pattern, e),
+            )
+        })?;
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), re.clone());
+        Ok(re)
+    })
+}
+
+/// A match as a map: the whole match under `0`, each group under its
+/// number and, for named groups, also under its name. Groups that took no
+/// part in the match are nil.
+fn captures(re: &Regex, caps: &Captures) -> Value {
+    let group = |idx: usize| match caps.get(idx) {
+        Some(m) => Value::String(m.as_str().to_string()),
+        None => Value::Nil,
+    };
+    let mut map = Map::new();
+    for idx in 0..caps.len() {
+        map_insert(&mut map, idx.to_string(), group(idx));
+    }
+    for (idx, name) in re.capture_names().enumerate() {
+        if let Some(name) = name {
+            map_insert(&mut map, name.to_string(), group(idx));
+        }
+    }
+    Value::Map(map)
+}
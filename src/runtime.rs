@@ -4,56 +4,188 @@
 // License: MPL 2.0
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::parser::Statement;
-use crate::value::Value;
+use crate::files::FileHandle;
+use crate::process::Process;
+use crate::socket::{Datagram, Listener, Socket};
+use crate::value::{Function, Scope, Value};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::net::TcpStream;
+use std::io;
+use std::rc::Rc;
+use std::time::Duration;
 
 pub struct Runtime {
     variables: HashMap<String, Value>,
-    sockets: HashMap<String, TcpStream>,
-    functions: HashMap<String, Vec<Statement>>,
+    scopes: Vec<Scope>,
+    sockets: HashMap<String, Socket>,
+    listeners: HashMap<String, Listener>,
+    datagrams: HashMap<String, Datagram>,
+    /// Kept by name so they also cover connections opened later.
+    timeouts: HashMap<String, Duration>,
+    files: HashMap<String, FileHandle>,
+    processes: HashMap<String, Process>,
+    spawned: usize,
+    functions: HashMap<String, Rc<Function>>,
 }
 
 impl Runtime {
     pub fn new() -> Self {
         Runtime {
             variables: HashMap::new(),
+            scopes: Vec::new(),
             sockets: HashMap::new(),
+            listeners: HashMap::new(),
+            datagrams: HashMap::new(),
+            timeouts: HashMap::new(),
+            files: HashMap::new(),
+            processes: HashMap::new(),
+            spawned: 0,
             functions: HashMap::new(),
         }
     }
 
     pub fn get_var(&self, name: &str) -> Value {
-        self.variables.get(name).cloned().unwrap_or(Value::Nil)
+        self.lookup_var(name).unwrap_or(Value::Nil)
     }
 
+    pub fn lookup_var(&self, name: &str) -> Option<Value> {
+        if let Some(cell) = self.scopes.last().and_then(|scope| scope.get(name)) {
+            return Some(cell.borrow().clone());
+        }
+        self.variables.get(name).cloned()
+    }
+
+    /// New names become locals of the current frame, or globals outside calls.
     pub fn set_var(&mut self, name: String, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            if let Some(cell) = scope.get(&name) {
+                *cell.borrow_mut() = value;
+            } else if let Some(slot) = self.variables.get_mut(&name) {
+                *slot = value;
+            } else {
+                scope.insert(name, Rc::new(RefCell::new(value)));
+            }
+            return;
+        }
         self.variables.insert(name, value);
     }
 
-    pub fn get_socket(&mut self, name: &str) -> Option<&mut TcpStream> {
+    pub fn push_scope(&mut self, locals: Scope) {
+        self.scopes.push(locals);
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Locals of the innermost call frame, shared with closures created there.
+    pub fn current_locals(&self) -> Scope {
+        self.scopes.last().cloned().unwrap_or_default()
+    }
+
+    pub fn get_socket(&mut self, name: &str) -> Option<&mut Socket> {
         self.sockets.get_mut(name)
     }
 
-    pub fn set_socket(&mut self, name: String, stream: TcpStream) {
-        self.sockets.insert(name, stream);
+    pub fn set_socket(&mut self, name: String, socket: Socket) -> io::Result<()> {
+        socket.set_timeout(self.timeout(&name))?;
+        self.sockets.insert(name, socket);
+        Ok(())
     }
 
     pub fn remove_socket(&mut self, name: &str) {
         self.sockets.remove(name);
+        self.listeners.remove(name);
+        self.datagrams.remove(name);
     }
 
-    #[allow(dead_code)]
-    pub fn has_socket(&self, name: &str) -> bool {
-        self.sockets.contains_key(name)
+    pub fn get_listener(&mut self, name: &str) -> Option<&mut Listener> {
+        self.listeners.get_mut(name)
+    }
+
+    pub fn set_listener(&mut self, name: String, mut listener: Listener) {
+        listener.set_timeout(self.timeout(&name));
+        self.listeners.insert(name, listener);
+    }
+
+    pub fn get_datagram(&self, name: &str) -> Option<&Datagram> {
+        self.datagrams.get(name)
+    }
+
+    pub fn set_datagram(&mut self, name: String, datagram: Datagram) -> io::Result<()> {
+        datagram.set_timeout(self.timeout(&name))?;
+        self.datagrams.insert(name, datagram);
+        Ok(())
+    }
+
+    pub fn timeout(&self, name: &str) -> Option<Duration> {
+        self.timeouts.get(name).copied()
+    }
+
+    pub fn set_timeout(&mut self, name: &str, timeout: Option<Duration>) -> io::Result<()> {
+        match timeout {
+            Some(timeout) => self.timeouts.insert(name.to_string(), timeout),
+            None => self.timeouts.remove(name),
+        };
+        if let Some(listener) = self.listeners.get_mut(name) {
+            listener.set_timeout(timeout);
+        }
+        if let Some(datagram) = self.datagrams.get(name) {
+            datagram.set_timeout(timeout)?;
+        }
+        match self.sockets.get(name) {
+            Some(socket) => socket.set_timeout(timeout),
+            None => Ok(()),
+        }
+    }
+
+    pub fn socket_status(&self, name: &str) -> &'static str {
+        match self.sockets.get(name) {
+            Some(socket) => socket.status(),
+            None if self.listeners.contains_key(name) => "listening",
+            None if self.datagrams.contains_key(name) => "open",
+            None => "closed",
+        }
+    }
+
+    pub fn socket_error(&self, name: &str) -> Option<String> {
+        self.sockets
+            .get(name)
+            .and_then(|socket| socket.last_error().map(str::to_string))
+    }
+
+    pub fn get_file(&mut self, name: &str) -> Option<&mut FileHandle> {
+        self.files.get_mut(name)
+    }
+
+    pub fn set_file(&mut self, name: String, file: FileHandle) {
+        self.files.insert(name, file);
+    }
+
+    pub fn remove_file(&mut self, name: &str) -> bool {
+        self.files.remove(name).is_some()
+    }
+
+    pub fn add_process(&mut self, process: Process) -> String {
+        self.spawned += 1;
+        let handle = format!("proc{}", self.spawned);
+        self.processes.insert(handle.clone(), process);
+        handle
+    }
+
+    pub fn get_process(&mut self, handle: &str) -> Option<&mut Process> {
+        self.processes.get_mut(handle)
+    }
+
+    pub fn remove_process(&mut self, handle: &str) -> Option<Process> {
+        self.processes.remove(handle)
     }
 
-    pub fn define_function(&mut self, name: String, body: Vec<Statement>) {
-        self.functions.insert(name, body);
+    pub fn define_function(&mut self, name: String, function: Rc<Function>) {
+        self.functions.insert(name, function);
     }
 
-    pub fn get_function(&self, name: &str) -> Option<Vec<Statement>> {
+    pub fn get_function(&self, name: &str) -> Option<Rc<Function>> {
         self.functions.get(name).cloned()
     }
 
@@ -0,0 +1,572 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, StreamOwned};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to sleep between polls while waiting with a timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Chunk size used when a read has no natural upper bound.
+const READ_CHUNK: usize = 4096;
+
+/// Cap on a single read, whatever maximum the script asks for.
+const MAX_READ: usize = 1 << 20;
+
+/// Largest payload a UDP datagram can carry.
+const MAX_DATAGRAM: usize = 65535;
+
+/// Prefix marking an address as a Unix socket path, as in `unix:/run/app.sock`.
+const UNIX_PREFIX: &str = "unix:";
+
+/// Connection state reported by `sockstatus`.
+#[derive(Debug, Clone)]
+pub enum SocketState {
+    Open,
+    Eof,
+    Error(String),
+}
+
+/// True for the errors a read or write timeout produces.
+pub fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Timeouts get their own `timeout` kind so scripts can catch and retry.
+pub fn socket_error(context: String, error: io::Error) -> Error {
+    let kind = if is_timeout(&error) {
+        "timeout"
+    } else {
+        "socket"
+    };
+    Error::new(kind, format!("{}: {}", context, error))
+}
+
+#[cfg(not(unix))]
+fn unix_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix sockets are not supported on this platform",
+    )
+}
+
+/// `addr` is `host:port` or `unix:/path`; the timeout only applies to TCP.
+pub fn connect(addr: &str, timeout: Option<Duration>) -> io::Result<Socket> {
+    if let Some(path) = addr.strip_prefix(UNIX_PREFIX) {
+        #[cfg(unix)]
+        return UnixStream::connect(path).map(|stream| Socket::new(Stream::Unix(stream)));
+        #[cfg(not(unix))]
+        return Err(unix_unsupported());
+    }
+
+    connect_tcp(addr, timeout).map(|stream| Socket::new(Stream::Tcp(stream)))
+}
+
+fn connect_tcp(addr: &str, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return TcpStream::connect(addr),
+    };
+
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no addresses resolved");
+    for resolved in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&resolved, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// The handshake completes here, so certificate problems surface at connect.
+pub fn connect_tls(
+    addr: &str,
+    server: ServerName<'static>,
+    config: Arc<ClientConfig>,
+    timeout: Option<Duration>,
+) -> io::Result<Socket> {
+    let tcp = connect_tcp(addr, timeout)?;
+    tcp.set_read_timeout(timeout)?;
+    tcp.set_write_timeout(timeout)?;
+
+    let conn = ClientConnection::new(config, server).map_err(io::Error::other)?;
+    let mut stream = StreamOwned::new(conn, tcp);
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    Ok(Socket::new(Stream::Tls(Box::new(stream))))
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Stream {
+    fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+            #[cfg(unix)]
+            Stream::Unix(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+            Stream::Tls(stream) => {
+                stream.sock.set_read_timeout(timeout)?;
+                stream.sock.set_write_timeout(timeout)
+            }
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+            Stream::Tls(stream) => stream.sock.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+            // Plenty of servers hang up without a close_notify; treat that
+            // as an ordinary EOF like a plain TCP close.
+            Stream::Tls(stream) => match stream.read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+                result => result,
+            },
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Drop for Stream {
+    // Send close_notify so the server can tell a close from a truncation.
+    fn drop(&mut self) {
+        if let Stream::Tls(stream) = self {
+            stream.conn.send_close_notify();
+            let _ = stream.flush();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn unix_addr(addr: std::os::unix::net::SocketAddr) -> String {
+    match addr.as_pathname() {
+        Some(path) => format!("{}{}", UNIX_PREFIX, path.display()),
+        None => "unix".to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn no_port() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "Unix sockets have no port")
+}
+
+pub struct Socket {
+    stream: Stream,
+    state: SocketState,
+    /// Received but not yet handed to the script.
+    buffer: Vec<u8>,
+}
+
+impl Socket {
+    fn new(stream: Stream) -> Self {
+        Socket {
+            stream,
+            state: SocketState::Open,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        match self.state {
+            SocketState::Open => "open",
+            SocketState::Eof => "eof",
+            SocketState::Error(_) => "error",
+        }
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        match &self.state {
+            SocketState::Error(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<String> {
+        match &self.stream {
+            Stream::Tcp(stream) => stream.peer_addr().map(|addr| addr.to_string()),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.peer_addr().map(unix_addr),
+            Stream::Tls(stream) => stream.sock.peer_addr().map(|addr| addr.to_string()),
+        }
+    }
+
+    pub fn local_port(&self) -> io::Result<u16> {
+        match &self.stream {
+            Stream::Tcp(stream) => stream.local_addr().map(|addr| addr.port()),
+            #[cfg(unix)]
+            Stream::Unix(_) => Err(no_port()),
+            Stream::Tls(stream) => stream.sock.local_addr().map(|addr| addr.port()),
+        }
+    }
+
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_timeout(timeout)
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let result = self
+            .stream
+            .write_all(data)
+            .and_then(|_| self.stream.flush());
+        self.track(result)
+    }
+
+    /// An empty result means the peer closed the connection.
+    pub fn receive(&mut self, max: usize) -> io::Result<Vec<u8>> {
+        if self.buffer.is_empty() {
+            self.fill(max)?;
+        }
+        let take = max.min(self.buffer.len());
+        Ok(self.buffer.drain(..take).collect())
+    }
+
+    /// A line without its terminator; `None` at EOF.
+    pub fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.read_line_max(usize::MAX)
+    }
+
+    /// A line longer than `max` bytes is an `InvalidData` error.
+    pub fn read_line_max(&mut self, max: usize) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let newline = self.buffer.iter().position(|&b| b == b'\n');
+            if newline.unwrap_or(self.buffer.len()) > max.saturating_add(1) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line longer than {} bytes", max),
+                ));
+            }
+            if let Some(pos) = newline {
+                let mut line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Ok(Some(line));
+            }
+            if self.fill(READ_CHUNK)? == 0 {
+                return Ok(if self.buffer.is_empty() {
+                    None
+                } else {
+                    Some(std::mem::take(&mut self.buffer))
+                });
+            }
+        }
+    }
+
+    /// Reads in chunks so memory only grows as data arrives. On an early
+    /// close the error is `UnexpectedEof` and the data stays buffered.
+    pub fn read_exact(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        while self.buffer.len() < n {
+            if self.fill(READ_CHUNK)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "connection closed after {} of {} bytes",
+                        self.buffer.len(),
+                        n
+                    ),
+                ));
+            }
+        }
+        Ok(self.buffer.drain(..n).collect())
+    }
+
+    pub fn read_to_end(&mut self) -> io::Result<Vec<u8>> {
+        while self.fill(READ_CHUNK)? > 0 {}
+        Ok(std::mem::take(&mut self.buffer))
+    }
+
+    /// Whether a read would return without blocking.
+    pub fn is_readable(&mut self) -> io::Result<bool> {
+        if !self.buffer.is_empty() || !matches!(self.state, SocketState::Open) {
+            return Ok(true);
+        }
+
+        self.stream.set_nonblocking(true)?;
+        let result = self.fill(READ_CHUNK);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns how many bytes arrived; zero means EOF.
+    fn fill(&mut self, max: usize) -> io::Result<usize> {
+        if matches!(self.state, SocketState::Eof) {
+            return Ok(0);
+        }
+
+        let mut chunk = vec![0; max.min(MAX_READ)];
+        let result = self.stream.read(&mut chunk);
+        let n = self.track(result)?;
+        if n == 0 && max > 0 {
+            self.state = SocketState::Eof;
+        }
+        self.buffer.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    /// Timeouts leave the connection usable, so they are not recorded.
+    fn track<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        if let Err(e) = &result {
+            if !is_timeout(e) {
+                self.state = SocketState::Error(e.to_string());
+            }
+        }
+        result
+    }
+}
+
+enum Acceptor {
+    Tcp(TcpListener),
+    /// Keeps the path so the socket file can be removed on close.
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+/// Connections `sockselect` finds waiting are parked in `pending`.
+pub struct Listener {
+    inner: Acceptor,
+    pending: VecDeque<Stream>,
+    timeout: Option<Duration>,
+}
+
+impl Listener {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let inner = match addr.strip_prefix(UNIX_PREFIX) {
+            #[cfg(unix)]
+            Some(path) => Acceptor::Unix(UnixListener::bind(path)?, PathBuf::from(path)),
+            #[cfg(not(unix))]
+            Some(_) => return Err(unix_unsupported()),
+            None => Acceptor::Tcp(TcpListener::bind(addr)?),
+        };
+        Ok(Listener {
+            inner,
+            pending: VecDeque::new(),
+            timeout: None,
+        })
+    }
+
+    pub fn local_port(&self) -> io::Result<u16> {
+        match &self.inner {
+            Acceptor::Tcp(listener) => listener.local_addr().map(|addr| addr.port()),
+            #[cfg(unix)]
+            Acceptor::Unix(..) => Err(no_port()),
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// `None` means the timeout expired.
+    pub fn accept(&mut self) -> io::Result<Option<Socket>> {
+        if let Some(stream) = self.pending.pop_front() {
+            return Ok(Some(Socket::new(stream)));
+        }
+
+        let deadline = match self.timeout {
+            Some(timeout) => Instant::now() + timeout,
+            None => {
+                return self
+                    .accept_blocking()
+                    .map(|stream| Some(Socket::new(stream)))
+            }
+        };
+
+        loop {
+            if let Some(stream) = self.try_accept()? {
+                return Ok(Some(Socket::new(stream)));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    pub fn is_readable(&mut self) -> io::Result<bool> {
+        if self.pending.is_empty() {
+            if let Some(stream) = self.try_accept()? {
+                self.pending.push_back(stream);
+            }
+        }
+        Ok(!self.pending.is_empty())
+    }
+
+    fn accept_blocking(&self) -> io::Result<Stream> {
+        match &self.inner {
+            Acceptor::Tcp(listener) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            #[cfg(unix)]
+            Acceptor::Unix(listener, _) => {
+                listener.accept().map(|(stream, _)| Stream::Unix(stream))
+            }
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match &self.inner {
+            Acceptor::Tcp(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Acceptor::Unix(listener, _) => listener.set_nonblocking(nonblocking),
+        }
+    }
+
+    fn try_accept(&mut self) -> io::Result<Option<Stream>> {
+        self.set_nonblocking(true)?;
+        let result = self.accept_blocking();
+        self.set_nonblocking(false)?;
+
+        match result {
+            Ok(stream) => {
+                stream.set_nonblocking(false)?;
+                Ok(Some(stream))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Acceptor::Unix(_, path) = &self.inner {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub struct Datagram {
+    inner: UdpSocket,
+}
+
+impl Datagram {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        UdpSocket::bind(addr).map(|inner| Datagram { inner })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)?;
+        self.inner.set_write_timeout(timeout)
+    }
+
+    pub fn send_to(&self, data: &[u8], addr: &str) -> io::Result<()> {
+        let sent = self.inner.send_to(data, addr)?;
+        if sent < data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                format!("datagram truncated to {} of {} bytes", sent, data.len()),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn receive(&self) -> io::Result<(Vec<u8>, SocketAddr)> {
+        let mut buf = vec![0; MAX_DATAGRAM];
+        let (n, from) = self.inner.recv_from(&mut buf)?;
+        buf.truncate(n);
+        Ok((buf, from))
+    }
+
+    pub fn is_readable(&self) -> io::Result<bool> {
+        self.inner.set_nonblocking(true)?;
+        let mut probe = [0; 1];
+        let result = self.inner.peek_from(&mut probe);
+        self.inner.set_nonblocking(false)?;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Indexes for which `ready` is true, waiting up to `timeout` for one.
+pub fn select<F>(count: usize, timeout: Option<Duration>, mut ready: F) -> io::Result<Vec<usize>>
+where
+    F: FnMut(usize) -> io::Result<bool>,
+{
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        let mut readable = Vec::new();
+        for idx in 0..count {
+            if ready(idx)? {
+                readable.push(idx);
+            }
+        }
+
+        let expired = deadline.is_some_and(|d| Instant::now() >= d);
+        if !readable.is_empty() || expired {
+            return Ok(readable);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,252 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::value::Value;
+use std::str::Chars;
+
+const MAX_WIDTH: usize = 1024;
+
+pub fn call(name: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+    let result = match name {
+        "format" => {
+            let template = args.first().map(display).unwrap_or_default();
+            render(&template, args.get(1..).unwrap_or_default()).map(Value::String)
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn format_error(message: impl Into<String>) -> Error {
+    Error::new("format", message)
+}
+
+pub fn display(value: &Value) -> String {
+    match value {
+        Value::Array(arr) => format!("[Array({})]", arr.len()),
+        Value::Map(map) => format!("[Map({})]", map.len()),
+        Value::Nil => String::new(),
+        other => other.to_string(),
+    }
+}
+
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+fn parse_spec(chars: &mut Chars) -> Option<Spec> {
+    let mut spec = Spec {
+        left: false,
+        zero: false,
+        plus: false,
+        space: false,
+        width: 0,
+        precision: None,
+        conversion: ' ',
+    };
+    while let Some(c) = peek(chars) {
+        match c {
+            '-' => spec.left = true,
+            '0' => spec.zero = true,
+            '+' => spec.plus = true,
+            ' ' => spec.space = true,
+            _ => break,
+        }
+        chars.next();
+    }
+    spec.width = number(chars).unwrap_or(0);
+    if peek(chars) == Some('.') {
+        chars.next();
+        spec.precision = Some(number(chars).unwrap_or(0));
+    }
+    match chars.next() {
+        Some(c @ ('d' | 'i' | 's' | 'x' | 'X' | 'o' | 'b' | 'f')) => {
+            spec.conversion = c;
+            Some(spec)
+        }
+        _ => None,
+    }
+}
+
+fn peek(chars: &Chars) -> Option<char> {
+    chars.clone().next()
+}
+
+fn number(chars: &mut Chars) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some(c) = peek(chars).filter(|c| c.is_ascii_digit()) {
+        digits.push(c);
+        chars.next();
+    }
+    // Too many digits for a usize is simply over the limit.
+    (!digits.is_empty()).then(|| digits.parse().unwrap_or(usize::MAX))
+}
+
+/// The space flag is refused so `"91% full"` is not read as `% f`.
+pub fn is_template(template: &str, args: usize) -> bool {
+    let mut directives = 0;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        if peek(&chars) == Some('%') {
+            chars.next();
+            continue;
+        }
+        match parse_spec(&mut chars) {
+            Some(spec) if !spec.space => directives += 1,
+            _ => return false,
+        }
+    }
+    directives > 0 && directives == args
+}
+
+pub fn render(template: &str, args: &[Value]) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        if peek(&chars) == Some('%') {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+        let start = template.len() - chars.as_str().len() - 1;
+        let spec = parse_spec(&mut chars).ok_or_else(|| {
+            let end = template.len() - chars.as_str().len();
+            format_error(format!(
+                "Unknown format directive '{}'",
+                &template[start..end]
+            ))
+        })?;
+        if spec.width > MAX_WIDTH || spec.precision.is_some_and(|p| p > MAX_WIDTH) {
+            let end = template.len() - chars.as_str().len();
+            return Err(format_error(format!(
+                "Width or precision in '{}' is over the limit of {}",
+                &template[start..end],
+                MAX_WIDTH
+            )));
+        }
+        let value = args.next().ok_or_else(|| {
+            format_error(format!(
+                "Not enough arguments for the directives in '{}'",
+                template
+            ))
+        })?;
+        out.push_str(&directive(&spec, value)?);
+    }
+    if args.next().is_some() {
+        return Err(format_error(format!(
+            "Too many arguments for format '{}'",
+            template
+        )));
+    }
+    Ok(out)
+}
+
+fn directive(spec: &Spec, value: &Value) -> Result<String, Error> {
+    let (sign, digits) = match spec.conversion {
+        's' => {
+            let text = display(value);
+            let text = match spec.precision {
+                Some(max) => text.chars().take(max).collect(),
+                None => text,
+            };
+            return Ok(pad(spec, "", &text, false));
+        }
+        'f' => {
+            let n = float_arg(spec, value)?;
+            let digits = format!("{:.*}", spec.precision.unwrap_or(6), n.abs());
+            return Ok(pad(spec, sign(spec, n < 0.0), &digits, true));
+        }
+        'd' | 'i' => {
+            let n = int_arg(spec, value)?;
+            (sign(spec, n < 0), n.unsigned_abs().to_string())
+        }
+        // Negative numbers show their two's complement, as in C.
+        'x' => ("", format!("{:x}", int_arg(spec, value)?)),
+        'X' => ("", format!("{:X}", int_arg(spec, value)?)),
+        'o' => ("", format!("{:o}", int_arg(spec, value)?)),
+        'b' => ("", format!("{:b}", int_arg(spec, value)?)),
+        _ => unreachable!("parse_spec only accepts known conversions"),
+    };
+    // For integers the precision is the minimum number of digits, and
+    // zero padding gives way to it as in C.
+    match spec.precision {
+        Some(min) => {
+            let digits = format!("{:0>width$}", digits, width = min);
+            Ok(pad(spec, sign, &digits, false))
+        }
+        None => Ok(pad(spec, sign, &digits, true)),
+    }
+}
+
+fn sign(spec: &Spec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+fn pad(spec: &Spec, sign: &str, digits: &str, zero_allowed: bool) -> String {
+    let len = sign.chars().count() + digits.chars().count();
+    let fill = spec.width.saturating_sub(len);
+    if spec.left {
+        format!("{}{}{}", sign, digits, " ".repeat(fill))
+    } else if spec.zero && zero_allowed {
+        format!("{}{}{}", sign, "0".repeat(fill), digits)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), sign, digits)
+    }
+}
+
+fn int_arg(spec: &Spec, value: &Value) -> Result<i64, Error> {
+    match value {
+        Value::Int(n) => Ok(*n),
+        Value::Bool(b) => Ok(*b as i64),
+        Value::String(s) | Value::Number(s) => s.trim().parse().map_err(|_| mismatch(spec, value)),
+        _ => Err(mismatch(spec, value)),
+    }
+}
+
+/// Minilux has no floats, so `%f` takes ints, numeric strings and numbers.
+fn float_arg(spec: &Spec, value: &Value) -> Result<f64, Error> {
+    match value {
+        Value::Int(n) => Ok(*n as f64),
+        Value::String(s) | Value::Number(s) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| mismatch(spec, value)),
+        _ => Err(mismatch(spec, value)),
+    }
+}
+
+fn mismatch(spec: &Spec, value: &Value) -> Error {
+    format_error(format!(
+        "%{} expects a number, got '{}'",
+        spec.conversion, value
+    ))
+}
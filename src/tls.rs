@@ -0,0 +1,206 @@
+// The Minilux Programming Language
+// Version: 0.1.0
+// Author: Alexia Michelle <https://minilux.org>
+// License: MPL 2.0
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::error::Error;
+use crate::socket::{self, socket_error, Socket};
+use crate::value::{map_get, Value};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::io;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+pub struct TlsOptions {
+    server_name: String,
+    ca_files: Vec<String>,
+    /// Accept any certificate. Only for testing against throwaway servers.
+    insecure: bool,
+}
+
+impl TlsOptions {
+    pub fn from_value(host: &str, options: &Value) -> Result<Self, Error> {
+        let options = match options {
+            Value::Map(map) => map.as_slice(),
+            Value::Nil => &[],
+            other => {
+                return Err(tls_error(format!(
+                    "TLS options must be a map, got '{}'",
+                    other
+                )))
+            }
+        };
+        let option = |key: &str| map_get(options, key);
+
+        let ca_files = match option("ca") {
+            None | Some(Value::Nil) => Vec::new(),
+            Some(Value::Array(paths)) => paths.iter().map(|p| p.to_string()).collect(),
+            Some(path) => vec![path.to_string()],
+        };
+        // IPv6 hosts arrive bracketed, as they are written in addresses.
+        let server_name = match option("sni") {
+            Some(name) => name.to_string(),
+            None => host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+        };
+
+        Ok(TlsOptions {
+            server_name,
+            ca_files,
+            insecure: option("insecure").is_some_and(|v| v.is_truthy()),
+        })
+    }
+
+    fn server_name(&self) -> Result<ServerName<'static>, Error> {
+        ServerName::try_from(self.server_name.clone())
+            .map_err(|_| tls_error(format!("Invalid TLS server name '{}'", self.server_name)))
+    }
+
+    fn client_config(&self) -> Result<Arc<ClientConfig>, Error> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| tls_error(format!("TLS setup failed: {}", e)))?;
+
+        let config = if self.insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+                .with_no_client_auth()
+        } else if self.ca_files.is_empty() {
+            builder
+                .with_root_certificates(system_roots()?)
+                .with_no_client_auth()
+        } else {
+            builder
+                .with_root_certificates(load_ca_files(&self.ca_files)?)
+                .with_no_client_auth()
+        };
+        Ok(Arc::new(config))
+    }
+}
+
+pub fn connect(
+    addr: &str,
+    options: &TlsOptions,
+    timeout: Option<Duration>,
+) -> Result<Socket, Error> {
+    socket::connect_tls(
+        addr,
+        options.server_name()?,
+        options.client_config()?,
+        timeout,
+    )
+    .map_err(|e| connect_error(format!("Failed to connect to {}", addr), e))
+}
+
+fn tls_error(message: impl Into<String>) -> Error {
+    Error::new("tls", message)
+}
+
+fn connect_error(context: String, error: io::Error) -> Error {
+    let is_tls = error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<rustls::Error>());
+    if is_tls {
+        tls_error(format!("{}: {}", context, error))
+    } else {
+        socket_error(context, error)
+    }
+}
+
+/// `SSL_CERT_FILE` and `SSL_CERT_DIR` override where they are looked for.
+fn system_roots() -> Result<Arc<RootCertStore>, Error> {
+    static ROOTS: OnceLock<Result<Arc<RootCertStore>, String>> = OnceLock::new();
+    ROOTS
+        .get_or_init(|| {
+            let found = rustls_native_certs::load_native_certs();
+            let mut roots = RootCertStore::empty();
+            let (added, _) = roots.add_parsable_certificates(found.certs);
+            if added == 0 {
+                let reason = match found.errors.first() {
+                    Some(e) => format!(": {}", e),
+                    None => String::new(),
+                };
+                return Err(format!("No system CA certificates found{}", reason));
+            }
+            Ok(Arc::new(roots))
+        })
+        .clone()
+        .map_err(tls_error)
+}
+
+fn load_ca_files(paths: &[String]) -> Result<Arc<RootCertStore>, Error> {
+    let mut roots = RootCertStore::empty();
+    for path in paths {
+        let certs = CertificateDer::pem_file_iter(path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| tls_error(format!("Failed to read CA file '{}': {}", path, e)))?;
+        if certs.is_empty() {
+            return Err(tls_error(format!("No certificates in CA file '{}'", path)));
+        }
+        for cert in certs {
+            roots
+                .add(cert)
+                .map_err(|e| tls_error(format!("Invalid certificate in '{}': {}", path, e)))?;
+        }
+    }
+    Ok(Arc::new(roots))
+}
+
+/// Handshake signatures are still checked, so the connection is
+/// encrypted, just not authenticated.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
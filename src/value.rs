@@ -4,29 +4,73 @@
 // License: MPL 2.0
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::parser::Statement;
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
 
 /// Represents a value in the minilux language
 #[derive(Debug, Clone)]
 pub enum Value {
     Int(i64),
+    Bool(bool),
     String(String),
+    /// A JSON number with no exact integer form (`0.75`, `1e3`, or too
+    /// large for 64 bits), kept as written so it encodes back the same.
+    Number(String),
+    Bytes(Vec<u8>),
     Array(Vec<Value>),
+    Map(Map),
+    Function(Rc<Function>),
     Nil,
 }
 
+/// String-keyed map that remembers insertion order, so printing and
+/// iterating a map is predictable.
+pub type Map = Vec<(String, Value)>;
+
+pub fn map_get<'a>(map: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+    map.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+pub fn map_insert(map: &mut Map, key: String, value: Value) {
+    match map.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, slot)) => *slot = value,
+        None => map.push((key, value)),
+    }
+}
+
+/// Variables of a call frame. Each one is a shared cell, so a closure
+/// created in the frame reads and updates the same variable.
+pub type Scope = HashMap<String, Rc<RefCell<Value>>>;
+
+/// A callable value: either a named `func` definition or an anonymous
+/// `func($x) { ... }` closure together with the locals it captured.
+#[derive(Debug)]
+pub struct Function {
+    pub name: Option<String>,
+    /// `None` for a `func name { ... }` block, which runs in the scope of
+    /// its caller rather than in a frame of its own.
+    pub params: Option<Vec<String>>,
+    pub body: Vec<Statement>,
+    pub captured: Scope,
+}
+
 impl Value {
-    /// Convert to string representation
-    pub fn to_string(&self) -> String {
+    /// Name of the value's type, for error messages
+    pub fn type_name(&self) -> &'static str {
         match self {
-            Value::Int(n) => n.to_string(),
-            Value::String(s) => s.clone(),
-            Value::Array(arr) => {
-                let items: Vec<String> = arr.iter().map(|v| v.to_string()).collect();
-                format!("[{}]", items.join(", "))
-            }
-            Value::Nil => "nil".to_string(),
+            Value::Int(_) => "int",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Bytes(_) => "bytes",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Function(_) => "function",
+            Value::Nil => "nil",
         }
     }
 
@@ -34,18 +78,33 @@ impl Value {
     pub fn to_int(&self) -> i64 {
         match self {
             Value::Int(n) => *n,
-            Value::String(s) => s.parse().unwrap_or(0),
-            Value::Array(_) => 0,
+            Value::Bool(b) => *b as i64,
+            Value::String(s) | Value::Number(s) => s.parse().unwrap_or(0),
+            Value::Bytes(_) | Value::Array(_) | Value::Map(_) | Value::Function(_) => 0,
             Value::Nil => 0,
         }
     }
 
+    /// Raw bytes for writing to a socket or file: a byte buffer as is,
+    /// anything else as its UTF-8 text.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Bytes(bytes) => bytes.clone(),
+            other => other.to_string().into_bytes(),
+        }
+    }
+
     /// Check if value is truthy
     pub fn is_truthy(&self) -> bool {
         match self {
             Value::Int(n) => *n != 0,
+            Value::Bool(b) => *b,
             Value::String(s) => !s.is_empty(),
+            Value::Number(s) => s.parse::<f64>().is_ok_and(|n| n != 0.0),
+            Value::Bytes(bytes) => !bytes.is_empty(),
             Value::Array(arr) => !arr.is_empty(),
+            Value::Map(map) => !map.is_empty(),
+            Value::Function(_) => true,
             Value::Nil => false,
         }
     }
@@ -54,19 +113,74 @@ impl Value {
     pub fn equals(&self, other: &Value) -> bool {
         match (self, other) {
             (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
             (Value::Nil, Value::Nil) => true,
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(k, v)| map_get(b, k).is_some_and(|other| v.equals(other)))
+            }
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.equals(y))
+            }
             (Value::Int(a), Value::String(b)) => a.to_string() == *b,
             (Value::String(a), Value::Int(b)) => a == &b.to_string(),
+            (Value::Number(a), Value::Number(b) | Value::String(b))
+            | (Value::String(a), Value::Number(b)) => a == b,
             _ => false,
         }
     }
 
+    /// Total order for sorting: by kind (nil, bools, numbers and numeric
+    /// strings, strings, bytes, arrays, maps, functions), then by value
+    pub fn sort_cmp(&self, other: &Value) -> Ordering {
+        fn rank(value: &Value) -> (u8, Option<f64>) {
+            match value {
+                Value::Nil => (0, None),
+                Value::Bool(_) => (1, None),
+                Value::Int(n) => (2, Some(*n as f64)),
+                Value::Number(s) => (2, s.parse().ok()),
+                Value::String(s) => match s.parse::<i64>() {
+                    Ok(n) => (2, Some(n as f64)),
+                    Err(_) => (3, None),
+                },
+                Value::Bytes(_) => (4, None),
+                Value::Array(_) => (5, None),
+                Value::Map(_) => (6, None),
+                Value::Function(_) => (7, None),
+            }
+        }
+
+        let ((a_rank, a_num), (b_rank, b_num)) = (rank(self), rank(other));
+        a_rank.cmp(&b_rank).then_with(|| match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            _ if a_rank == 2 => match (a_num, b_num) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            },
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+            (Value::Array(a), Value::Array(b)) => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| x.sort_cmp(y))
+                .find(|order| order.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            _ => Ordering::Equal,
+        })
+    }
+
     /// Compare two values
     pub fn compare(&self, other: &Value) -> Option<Ordering> {
         match (self, other) {
             (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
             (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
             (Value::Int(a), Value::String(b)) => {
                 if let Ok(b_int) = b.parse::<i64>() {
                     Some(a.cmp(&b_int))
@@ -81,6 +195,14 @@ impl Value {
                     None
                 }
             }
+            (Value::Number(_), _) | (_, Value::Number(_)) => {
+                let as_f64 = |v: &Value| match v {
+                    Value::Int(n) => Some(*n as f64),
+                    Value::String(s) | Value::Number(s) => s.parse::<f64>().ok(),
+                    _ => None,
+                };
+                as_f64(self)?.partial_cmp(&as_f64(other)?)
+            }
             _ => None,
         }
     }
@@ -88,10 +210,16 @@ impl Value {
     /// Add two values
     pub fn add(&self, other: &Value) -> Value {
         match (self, other) {
+            // Numbers kept as text join like the strings they once were.
+            (Value::Number(a), b) => Value::String(a.clone()).add(b),
+            (a, Value::Number(b)) => a.add(&Value::String(b.clone())),
             (Value::Int(a), Value::Int(b)) => Value::Int(a + b),
             (Value::String(a), Value::String(b)) => Value::String(format!("{}{}", a, b)),
+            (Value::Bytes(a), Value::Bytes(b)) => Value::Bytes([a.as_slice(), b].concat()),
             (Value::Int(a), Value::String(b)) => Value::String(format!("{}{}", a, b)),
             (Value::String(a), Value::Int(b)) => Value::String(format!("{}{}", a, b)),
+            (Value::Bool(a), Value::String(b)) => Value::String(format!("{}{}", a, b)),
+            (Value::String(a), Value::Bool(b)) => Value::String(format!("{}{}", a, b)),
             _ => Value::Nil,
         }
     }
@@ -142,7 +270,41 @@ impl Value {
 }
 
 impl fmt::Display for Value {
+    /// Convert to string representation
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) | Value::Number(s) => write!(f, "{}", s),
+            Value::Bytes(bytes) => {
+                // Shown as a literal: printable ASCII as is, the rest escaped.
+                write!(f, "b\"")?;
+                for &byte in bytes {
+                    match byte {
+                        b'"' => write!(f, "\\\"")?,
+                        b'\\' => write!(f, "\\\\")?,
+                        b'\n' => write!(f, "\\n")?,
+                        b'\r' => write!(f, "\\r")?,
+                        b'\t' => write!(f, "\\t")?,
+                        0x20..=0x7e => write!(f, "{}", byte as char)?,
+                        _ => write!(f, "\\x{:02x}", byte)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Array(arr) => {
+                let items: Vec<String> = arr.iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Map(map) => {
+                let items: Vec<String> = map.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", items.join(", "))
+            }
+            Value::Function(func) => match &func.name {
+                Some(name) => write!(f, "<function {}>", name),
+                None => write!(f, "<function>"),
+            },
+            Value::Nil => write!(f, "nil"),
+        }
     }
 }